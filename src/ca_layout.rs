@@ -0,0 +1,134 @@
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::str_error::StrError; //required by errf macro
+
+/// How the hash is split into directories below a content-addressable mirror.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Sharding {
+    /// `abc/def/abcdefgh...`, the layout warpforge uses.
+    #[default]
+    ThreeThree,
+    /// `ab/cd/abcdefgh...`
+    TwoTwo,
+    /// `abcdefgh...`, every ware in one directory.
+    Flat,
+}
+
+/// The layout of a content-addressable mirror, written as `abc/def`, `ab/cd` or `flat`,
+/// optionally prefixed with `packtype/` for mirrors which keep each packtype in its own directory
+/// (e.g. `packtype/ab/cd` places a tar ware at `tar/ab/cd/<hash>`).
+///
+/// A mirror declares its layout with a `layout` fragment on its URL, e.g.
+/// `ca+https://mirror.example.org#layout=ab/cd`, or it is set in the `caLayouts` map of the
+/// `--config` file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Layout {
+    pub sharding: Sharding,
+    pub packtype_prefix: bool,
+}
+
+impl FromStr for Layout {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Layout, Self::Err> {
+        let (packtype_prefix, rest) = match s.strip_prefix("packtype") {
+            Some("") => (true, "flat"),
+            Some(rest) if rest.starts_with('/') => (true, &rest[1..]),
+            _ => (false, s),
+        };
+        let sharding = match rest {
+            "abc/def" => Sharding::ThreeThree,
+            "ab/cd" => Sharding::TwoTwo,
+            "flat" => Sharding::Flat,
+            _ => {
+                return Err(errf!(
+                    r#"unknown layout "{s}", expected "abc/def", "ab/cd" or "flat", optionally prefixed with "packtype/""#
+                ))
+            }
+        };
+        Ok(Layout {
+            sharding,
+            packtype_prefix,
+        })
+    }
+}
+
+impl fmt::Display for Layout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.packtype_prefix {
+            write!(f, "packtype/")?;
+        }
+        match self.sharding {
+            Sharding::ThreeThree => write!(f, "abc/def"),
+            Sharding::TwoTwo => write!(f, "ab/cd"),
+            Sharding::Flat => write!(f, "flat"),
+        }
+    }
+}
+
+impl Layout {
+    /// Returns the path segments below the mirror's base URL at which a ware is stored.
+    pub fn segments<'a>(
+        &self,
+        pack_type: &'a str,
+        ware_hash: &'a str,
+    ) -> Result<Vec<&'a str>, Box<dyn Error>> {
+        let mut segments = Vec::new();
+        if self.packtype_prefix {
+            segments.push(pack_type);
+        }
+        let width = match self.sharding {
+            Sharding::ThreeThree => 3,
+            Sharding::TwoTwo => 2,
+            Sharding::Flat => 0,
+        };
+        // the hash must be longer than the shards so that the last segment is not a shard itself.
+        if ware_hash.len() <= width * 2
+            || !ware_hash.is_char_boundary(width)
+            || !ware_hash.is_char_boundary(width * 2)
+        {
+            return Err(errf!(
+                "ware_hash must be at least {} characters for layout {self}",
+                width * 2 + 1
+            ));
+        }
+        if width > 0 {
+            segments.push(&ware_hash[..width]);
+            segments.push(&ware_hash[width..width * 2]);
+        }
+        segments.push(ware_hash);
+        Ok(segments)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_segments() {
+        let cases = vec![
+            ("abc/def", "abc/def/abcdefgh"),
+            ("ab/cd", "ab/cd/abcdefgh"),
+            ("flat", "abcdefgh"),
+            ("packtype/abc/def", "tar/abc/def/abcdefgh"),
+            ("packtype/ab/cd", "tar/ab/cd/abcdefgh"),
+            ("packtype", "tar/abcdefgh"),
+        ];
+        for (layout, expected) in cases {
+            let parsed: Layout = layout.parse().unwrap();
+            let segments = parsed.segments("tar", "abcdefgh").unwrap();
+            assert_eq!(segments.join("/"), expected, "{layout}");
+        }
+        assert!("abc".parse::<Layout>().is_err());
+        let short = Layout::default().segments("tar", "abcdef");
+        assert!(short.is_err());
+        assert!("ab/cd"
+            .parse::<Layout>()
+            .unwrap()
+            .segments("tar", "abcde")
+            .is_ok());
+    }
+}
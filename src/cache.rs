@@ -0,0 +1,341 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::UNIX_EPOCH;
+
+use crate::catalog_mirrors::CatalogMirrorsCapsule;
+use crate::catalog_releases::{CatalogModuleCapsule, CatalogRelease};
+use crate::schema::{self, Schema, UnknownFields};
+use crate::walk::WalkError;
+
+pub const CACHE_FILE_NAME: &str = ".catalog-manifest-cache";
+const CACHE_FORMAT_VERSION: u32 = 1;
+
+/// A parsed catalog file as stored in the cache.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Doc {
+    Module(CatalogModuleCapsule),
+    Release(CatalogRelease),
+    Mirrors(CatalogMirrorsCapsule),
+}
+
+/// Implemented by every document type which can be stored in the cache.
+pub trait Cached: Sized + UnknownFields {
+    fn to_doc(&self) -> Doc;
+    fn from_doc(doc: &Doc) -> Option<Self>;
+}
+
+impl Cached for CatalogModuleCapsule {
+    fn to_doc(&self) -> Doc {
+        Doc::Module(self.clone())
+    }
+    fn from_doc(doc: &Doc) -> Option<Self> {
+        match doc {
+            Doc::Module(m) => Some(m.clone()),
+            _ => None,
+        }
+    }
+}
+
+impl Cached for CatalogRelease {
+    fn to_doc(&self) -> Doc {
+        Doc::Release(self.clone())
+    }
+    fn from_doc(doc: &Doc) -> Option<Self> {
+        match doc {
+            Doc::Release(r) => Some(r.clone()),
+            _ => None,
+        }
+    }
+}
+
+impl Cached for CatalogMirrorsCapsule {
+    fn to_doc(&self) -> Doc {
+        Doc::Mirrors(self.clone())
+    }
+    fn from_doc(doc: &Doc) -> Option<Self> {
+        match doc {
+            Doc::Mirrors(m) => Some(m.clone()),
+            _ => None,
+        }
+    }
+}
+
+/// The modification time and size of a file, used to decide whether a cache entry is still valid.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Stamp {
+    pub modified: (u64, u32),
+    pub len: u64,
+}
+
+impl Stamp {
+    pub fn of(path: &Path) -> std::io::Result<Stamp> {
+        let metadata = fs::metadata(path)?;
+        let modified = metadata
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        Ok(Stamp {
+            modified: (modified.as_secs(), modified.subsec_nanos()),
+            len: metadata.len(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Entry {
+    pub stamp: Stamp,
+    pub doc: Doc,
+}
+
+#[derive(Deserialize, Serialize)]
+struct CacheFile {
+    version: u32,
+    files: BTreeMap<String, Entry>,
+}
+
+/// An index of parsed catalog files keyed by their path relative to the catalog root.
+/// Files whose modification time and size are unchanged are not read again.
+/// A disabled cache (the default) always reads and parses files.
+#[derive(Default)]
+pub struct Cache {
+    root: Option<PathBuf>,
+    loaded: BTreeMap<String, Entry>,
+    fresh: Mutex<BTreeMap<String, Entry>>,
+    changed: AtomicBool,
+}
+
+impl Cache {
+    pub fn path(root: &Path) -> PathBuf {
+        root.join(CACHE_FILE_NAME)
+    }
+
+    pub fn exists(root: &Path) -> bool {
+        Cache::path(root).is_file()
+    }
+
+    /// An enabled cache with no entries. Every file will be parsed and recorded.
+    pub fn empty(root: &Path) -> Cache {
+        Cache {
+            root: Some(root.to_path_buf()),
+            changed: AtomicBool::new(true),
+            ..Default::default()
+        }
+    }
+
+    /// Loads the cache of the catalog at `root`.
+    /// A cache written by a different version of this tool is discarded with a warning.
+    pub fn open(root: &Path) -> Result<Cache, Box<dyn Error>> {
+        let path = Cache::path(root);
+        let contents = fs::read_to_string(&path)?;
+        let file: CacheFile = match serde_json::from_str(&contents) {
+            Ok(f) => f,
+            Err(e) => {
+                eprintln!(
+                    r#"WARNING: ignoring unreadable cache "{}": {e}"#,
+                    path.display()
+                );
+                return Ok(Cache::empty(root));
+            }
+        };
+        if file.version != CACHE_FORMAT_VERSION {
+            eprintln!(
+                r#"WARNING: ignoring cache "{}" with format version {}"#,
+                path.display(),
+                file.version
+            );
+            return Ok(Cache::empty(root));
+        }
+        Ok(Cache {
+            root: Some(root.to_path_buf()),
+            loaded: file.files,
+            ..Default::default()
+        })
+    }
+
+    fn key(&self, path: &Path) -> Option<String> {
+        let rel = path.strip_prefix(self.root.as_ref()?).ok()?;
+        Some(rel.to_string_lossy().replace('\\', "/"))
+    }
+
+    /// Returns the document at `path`, from the cache if it is still valid or by calling `parse` on
+    /// the file contents otherwise. Cached documents are checked against `schema` again.
+    pub fn load<T: Cached>(
+        &self,
+        path: &Path,
+        schema: Schema,
+        parse: impl FnOnce(&str) -> Result<T, WalkError>,
+    ) -> Result<T, WalkError> {
+        let key = match self.key(path) {
+            Some(k) => k,
+            None => return parse(&fs::read_to_string(path)?),
+        };
+        let stamp = Stamp::of(path)?;
+        if let Some(entry) = self.loaded.get(&key) {
+            if entry.stamp == stamp {
+                if let Some(doc) = T::from_doc(&entry.doc) {
+                    schema::check(path, &doc, schema)?;
+                    self.fresh.lock().unwrap().insert(key, entry.clone());
+                    return Ok(doc);
+                }
+            }
+        }
+        let doc = parse(&fs::read_to_string(path)?)?;
+        self.changed.store(true, Ordering::Relaxed);
+        let entry = Entry {
+            stamp,
+            doc: doc.to_doc(),
+        };
+        self.fresh.lock().unwrap().insert(key, entry);
+        Ok(doc)
+    }
+
+    /// Every entry which was read or parsed since the cache was opened.
+    pub fn entries(&self) -> BTreeMap<String, Entry> {
+        self.fresh.lock().unwrap().clone()
+    }
+
+    /// Writes the cache back to the catalog root if anything changed.
+    /// Entries which were not visited are kept as long as their file is unchanged.
+    pub fn save(&self) -> Result<(), Box<dyn Error>> {
+        let root = match &self.root {
+            Some(r) => r,
+            None => return Ok(()),
+        };
+        let mut files = self.entries();
+        let mut changed = self.changed.load(Ordering::Relaxed);
+        for (key, entry) in self.loaded.iter() {
+            if files.contains_key(key) {
+                continue;
+            }
+            match Stamp::of(&root.join(key)) {
+                Ok(stamp) if stamp == entry.stamp => {
+                    files.insert(key.clone(), entry.clone());
+                }
+                _ => changed = true,
+            }
+        }
+        if !changed {
+            return Ok(());
+        }
+        let file = CacheFile {
+            version: CACHE_FORMAT_VERSION,
+            files,
+        };
+        let path = Cache::path(root);
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_string(&file)?)?;
+        fs::rename(&tmp, &path)?;
+        Ok(())
+    }
+
+    /// Compares the loaded cache with a cache filled by a full walk of the catalog.
+    pub fn verify(&self, walked: &Cache) -> VerifyReport {
+        let walked = walked.entries();
+        let mut report = VerifyReport::default();
+        for (key, entry) in walked.iter() {
+            match self.loaded.get(key) {
+                None => report.missing.push(key.clone()),
+                Some(cached) if cached.stamp != entry.stamp => report.stale.push(key.clone()),
+                Some(cached) if cached.doc != entry.doc => report.mismatched.push(key.clone()),
+                Some(_) => report.valid += 1,
+            }
+        }
+        for key in self.loaded.keys() {
+            if !walked.contains_key(key) {
+                report.orphaned.push(key.clone());
+            }
+        }
+        report
+    }
+}
+
+/// The result of `cache verify`.
+/// `stale` entries have an outdated timestamp and would be re-read; `mismatched` entries have a
+/// current timestamp but different content, which means the cache is corrupt.
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct VerifyReport {
+    pub valid: usize,
+    pub missing: Vec<String>,
+    pub stale: Vec<String>,
+    pub mismatched: Vec<String>,
+    pub orphaned: Vec<String>,
+}
+
+impl VerifyReport {
+    pub fn is_ok(&self) -> bool {
+        self.mismatched.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::releases;
+    use crate::testutil::TempCatalog;
+    use crate::walk::WalkOptions;
+
+    fn walk(catalog: &TempCatalog, cache: Cache) -> (String, Cache) {
+        let opts = WalkOptions {
+            cache,
+            ..Default::default()
+        };
+        let result = releases::collect(&catalog.path, &opts).unwrap();
+        opts.cache.save().unwrap();
+        (serde_json::to_string(&result).unwrap(), opts.cache)
+    }
+
+    #[test]
+    fn test_cache_reuse_and_invalidation() {
+        let catalog = TempCatalog::new();
+        catalog.add_module("example.org/foo", &[("v1", &[("src", "tar:abcdefgh")])]);
+        let (uncached, _) = walk(&catalog, Cache::default());
+        let (first, _) = walk(&catalog, Cache::empty(&catalog.path));
+        assert_eq!(uncached, first);
+        assert!(Cache::exists(&catalog.path));
+
+        // an unchanged catalog is served entirely from the cache.
+        let cache = Cache::open(&catalog.path).unwrap();
+        assert_eq!(cache.loaded.len(), 2);
+        let (second, cache) = walk(&catalog, cache);
+        assert_eq!(first, second);
+        assert!(!cache.changed.load(Ordering::Relaxed));
+
+        // a changed file is parsed again.
+        catalog.write(
+            "example.org/foo/_releases/v1.json",
+            r#"{"releaseName": "v1", "items": {"bin": "tar:ijklmnopq"}, "metadata": {}}"#,
+        );
+        let cache = Cache::open(&catalog.path).unwrap();
+        let report = cache.verify(&walk(&catalog, Cache::empty(&catalog.path)).1);
+        assert_eq!(report.stale, vec!["example.org/foo/_releases/v1.json"]);
+        let (third, cache) = walk(&catalog, cache);
+        assert!(cache.changed.load(Ordering::Relaxed));
+        assert!(third.contains("example.org/foo:v1:bin"));
+    }
+
+    #[test]
+    fn test_verify_mismatch() {
+        let catalog = TempCatalog::new();
+        catalog.add_module("example.org/foo", &[("v1", &[("src", "tar:abcdefgh")])]);
+        walk(&catalog, Cache::empty(&catalog.path));
+        let mut cache = Cache::open(&catalog.path).unwrap();
+        let entry = cache
+            .loaded
+            .get_mut("example.org/foo/_releases/v1.json")
+            .unwrap();
+        if let Doc::Release(r) = &mut entry.doc {
+            r.items.insert("bin".into(), "tar:corrupt".into());
+        }
+        let (_, walked) = walk(&catalog, Cache::empty(&catalog.path));
+        let report = cache.verify(&walked);
+        assert_eq!(report.mismatched, vec!["example.org/foo/_releases/v1.json"]);
+        assert_eq!(report.valid, 1);
+        assert!(!report.is_ok());
+    }
+}
@@ -0,0 +1,42 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::path::Path;
+
+use crate::catalog_mirrors::CatalogMirrorsCapsule;
+use crate::releases::Module;
+use crate::walk::WalkOptions;
+use crate::wares::{ResolveOptions, ResolvedWares};
+use crate::{mirrors, releases, wares};
+
+/// Everything known about a catalog, read by a single walk of each kind.
+#[derive(Debug)]
+pub struct Catalog {
+    /// Modules sorted by name.
+    pub modules: Vec<Module>,
+    pub mirrors: CatalogMirrorsCapsule,
+    /// Catalog refs ("module:release:item") and their ware IDs.
+    pub refs: BTreeMap<String, String>,
+}
+
+impl Catalog {
+    pub fn load(dir: &Path, opts: &WalkOptions) -> Result<Catalog, Box<dyn Error>> {
+        let mirrors = mirrors::collect(dir, opts)?;
+        let mut modules = releases::collect_modules(dir, opts)?;
+        let refs = releases::catalog_refs(&modules)?;
+        modules.sort_by(|a, b| a.module.name.cmp(&b.module.name));
+        Ok(Catalog {
+            modules,
+            mirrors,
+            refs,
+        })
+    }
+
+    pub fn module(&self, name: &str) -> Option<&Module> {
+        self.modules.iter().find(|m| m.module.name == name)
+    }
+
+    /// Resolves every ware to its mirror locations, as the `wares` command does.
+    pub fn wares(&self, resolve: &ResolveOptions) -> Result<ResolvedWares, Box<dyn Error>> {
+        wares::join(self.mirrors.clone(), self.refs.clone(), resolve)
+    }
+}
@@ -1,9 +1,30 @@
 use std::error::Error;
+use std::fmt;
+use std::path::PathBuf;
 
+/// An error in a specific catalog file.
+/// `pointer` is a JSON pointer (RFC 6901) to the offending value, empty for the whole document.
+#[derive(Debug, PartialEq)]
 pub struct MalformedCatalog {
-    path: String,
-    message: String,
-    cause: dyn Error,
+    pub path: PathBuf,
+    pub pointer: String,
+    pub message: String,
+}
+
+impl fmt::Display for MalformedCatalog {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.pointer.is_empty() {
+            write!(f, "{}: {}", self.path.display(), self.message)
+        } else {
+            write!(
+                f,
+                "{}: {}: {}",
+                self.path.display(),
+                self.pointer,
+                self.message
+            )
+        }
+    }
 }
 
 impl Error for MalformedCatalog {}
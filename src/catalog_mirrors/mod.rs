@@ -2,12 +2,76 @@ use core::fmt;
 use std::{collections::BTreeSet, fmt::Display};
 
 use indexmap::IndexMap;
+use serde::de::{self, Deserializer, MapAccess, Visitor};
+use serde::ser::{SerializeMap, Serializer};
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Deserialize, Serialize, PartialEq)]
+pub const CAPSULE_V1: &str = "catalogmirrors.v1";
+
+// capsules are parsed once per file and rarely moved, so V1 is not boxed.
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum CatalogMirrorsCapsule {
-    #[serde(rename = "catalogmirrors.v1")]
     V1(CatalogMirrors),
+    /// A capsule written by a newer (or unrecognized) version of warpforge.
+    /// The raw JSON is kept so that it can be re-emitted or migrated untouched.
+    Unknown {
+        version: String,
+        data: serde_json::Value,
+    },
+}
+
+impl CatalogMirrorsCapsule {
+    pub fn version(&self) -> &str {
+        match self {
+            CatalogMirrorsCapsule::V1(_) => CAPSULE_V1,
+            CatalogMirrorsCapsule::Unknown { version, .. } => version,
+        }
+    }
+}
+
+impl Serialize for CatalogMirrorsCapsule {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(1))?;
+        match self {
+            CatalogMirrorsCapsule::V1(m) => map.serialize_entry(CAPSULE_V1, m)?,
+            CatalogMirrorsCapsule::Unknown { version, data } => {
+                map.serialize_entry(version, data)?
+            }
+        }
+        map.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for CatalogMirrorsCapsule {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct CapsuleVisitor;
+        impl<'de> Visitor<'de> for CapsuleVisitor {
+            type Value = CatalogMirrorsCapsule;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "a map with a single capsule version key")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+                let version: String = map
+                    .next_key()?
+                    .ok_or_else(|| de::Error::invalid_length(0, &self))?;
+                let capsule = match version.as_str() {
+                    CAPSULE_V1 => CatalogMirrorsCapsule::V1(map.next_value()?),
+                    _ => CatalogMirrorsCapsule::Unknown {
+                        data: map.next_value()?,
+                        version,
+                    },
+                };
+                if map.next_key::<de::IgnoredAny>()?.is_some() {
+                    return Err(de::Error::invalid_length(2, &self));
+                }
+                Ok(capsule)
+            }
+        }
+        deserializer.deserialize_map(CapsuleVisitor)
+    }
 }
 
 impl Display for CatalogMirrorsCapsule {
@@ -15,17 +79,20 @@ impl Display for CatalogMirrorsCapsule {
         let out = serde_json::to_string_pretty(self);
         match out {
             Ok(o) => write!(f, "{}", o),
-            Err(_) => Err(fmt::Error::default()),
+            Err(_) => Err(fmt::Error),
         }
     }
 }
 
-#[derive(Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
 pub struct CatalogMirrors {
     #[serde(rename = "byWare", default)]
     pub by_ware: IndexMap<String, BTreeSet<String>>,
     #[serde(rename = "byModule", default)]
     pub by_module: IndexMap<String, IndexMap<String, BTreeSet<String>>>,
+    /// Fields not known to this version of the crate, kept so they survive a round trip.
+    #[serde(flatten)]
+    pub extra: IndexMap<String, serde_json::Value>,
 }
 
 impl Display for CatalogMirrors {
@@ -33,7 +100,7 @@ impl Display for CatalogMirrors {
         let out = serde_json::to_string_pretty(self);
         match out {
             Ok(o) => write!(f, "{}", o),
-            Err(_) => Err(fmt::Error::default()),
+            Err(_) => Err(fmt::Error),
         }
     }
 }
@@ -43,6 +110,7 @@ impl Default for CatalogMirrors {
         CatalogMirrors {
             by_ware: IndexMap::new(),
             by_module: IndexMap::new(),
+            extra: IndexMap::new(),
         }
     }
 }
@@ -84,4 +152,46 @@ mod tests {
         let reserialized = pretty_json(obj).expect("serialization shouldn't fail");
         expect.assert_eq(&reserialized);
     }
+
+    #[test]
+    fn test_json_roundtrip_extra_fields() {
+        let expect = expect![[r#"
+        {
+            "catalogmirrors.v1": {
+                "byWare": {},
+                "byModule": {
+                    "warpsys.org/bash": {
+                        "tar": [
+                            "ca+https://warpsys-wares.s3.fr-par.scw.cloud"
+                        ]
+                    }
+                },
+                "comment": "maintained by the warpsys team",
+                "byRegion": {
+                    "eu": []
+                }
+            }
+        }"#]];
+        let obj: super::CatalogMirrorsCapsule = serde_json::from_str(expect.data).unwrap();
+        let reserialized = pretty_json(obj).expect("serialization shouldn't fail");
+        expect.assert_eq(&reserialized);
+    }
+
+    #[test]
+    fn test_json_roundtrip_unknown_version() {
+        let expect = expect![[r#"
+        {
+            "catalogmirrors.v9": {
+                "byWare": {},
+                "somethingNew": [
+                    1,
+                    2
+                ]
+            }
+        }"#]];
+        let obj: super::CatalogMirrorsCapsule = serde_json::from_str(expect.data).unwrap();
+        assert_eq!(obj.version(), "catalogmirrors.v9");
+        let reserialized = pretty_json(obj).expect("serialization shouldn't fail");
+        expect.assert_eq(&reserialized);
+    }
 }
@@ -1,27 +1,95 @@
-use std::fmt::Display;
+use std::fmt::{self, Display};
 
 use indexmap::IndexMap;
+use serde::de::{self, Deserializer, MapAccess, Visitor};
+use serde::ser::{SerializeMap, Serializer};
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Deserialize, Serialize, PartialEq)]
+pub const CAPSULE_V1: &str = "catalogmodule.v1";
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum CatalogModuleCapsule {
-    #[serde(rename = "catalogmodule.v1")]
     V1(CatalogModule),
+    /// A capsule written by a newer (or unrecognized) version of warpforge.
+    /// The raw JSON is kept so that it can be re-emitted or migrated untouched.
+    Unknown {
+        version: String,
+        data: serde_json::Value,
+    },
+}
+
+impl CatalogModuleCapsule {
+    pub fn version(&self) -> &str {
+        match self {
+            CatalogModuleCapsule::V1(_) => CAPSULE_V1,
+            CatalogModuleCapsule::Unknown { version, .. } => version,
+        }
+    }
+}
+
+impl Serialize for CatalogModuleCapsule {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(1))?;
+        match self {
+            CatalogModuleCapsule::V1(m) => map.serialize_entry(CAPSULE_V1, m)?,
+            CatalogModuleCapsule::Unknown { version, data } => {
+                map.serialize_entry(version, data)?
+            }
+        }
+        map.end()
+    }
 }
 
-#[derive(Debug, Deserialize, Serialize, PartialEq)]
+impl<'de> Deserialize<'de> for CatalogModuleCapsule {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct CapsuleVisitor;
+        impl<'de> Visitor<'de> for CapsuleVisitor {
+            type Value = CatalogModuleCapsule;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "a map with a single capsule version key")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+                let version: String = map
+                    .next_key()?
+                    .ok_or_else(|| de::Error::invalid_length(0, &self))?;
+                let capsule = match version.as_str() {
+                    CAPSULE_V1 => CatalogModuleCapsule::V1(map.next_value()?),
+                    _ => CatalogModuleCapsule::Unknown {
+                        data: map.next_value()?,
+                        version,
+                    },
+                };
+                if map.next_key::<de::IgnoredAny>()?.is_some() {
+                    return Err(de::Error::invalid_length(2, &self));
+                }
+                Ok(capsule)
+            }
+        }
+        deserializer.deserialize_map(CapsuleVisitor)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
 pub struct CatalogModule {
     pub name: String,
     pub releases: IndexMap<String, String>,
     pub metadata: IndexMap<String, String>,
+    /// Fields not known to this version of the crate, kept so they survive a round trip.
+    #[serde(flatten)]
+    pub extra: IndexMap<String, serde_json::Value>,
 }
 
-#[derive(Debug, Deserialize, Serialize, PartialEq)]
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
 pub struct CatalogRelease {
     #[serde(rename = "releaseName")]
     pub name: String,
     pub items: IndexMap<String, String>,
     pub metadata: IndexMap<String, String>,
+    /// Fields not known to this version of the crate, kept so they survive a round trip.
+    #[serde(flatten)]
+    pub extra: IndexMap<String, serde_json::Value>,
 }
 
 pub struct ReleaseItem {
@@ -67,4 +135,79 @@ mod tests {
         let reserialized = pretty_json(obj).expect("serialization shouldn't fail");
         expect.assert_eq(&reserialized);
     }
+
+    #[test]
+    fn test_module_json_roundtrip() {
+        let expect = expect![[r#"
+        {
+            "catalogmodule.v1": {
+                "name": "warpsys.org/bash",
+                "releases": {
+                    "v5.1.16": "zM5K3UHCUmBcpMN2RvkdECbqbHdmpeMZ7GeE2M28DpVkP9nPqNkhXmQ4EJrvyPBMT2ygm8A"
+                },
+                "metadata": {}
+            }
+        }"#]];
+        let obj: super::CatalogModuleCapsule = serde_json::from_str(expect.data).unwrap();
+        let reserialized = pretty_json(obj).expect("serialization shouldn't fail");
+        expect.assert_eq(&reserialized);
+    }
+
+    #[test]
+    fn test_json_roundtrip_extra_fields() {
+        let expect = expect![[r#"
+        {
+            "releaseName": "v5.1.16",
+            "items": {
+                "src": "tar:5K7rekQyv4YJphfwfssRsLqHtrL4G9bVmCuarnJyvNaCWzABt6ujLvRRQ48ppRqvNZ"
+            },
+            "metadata": {},
+            "signatures": [
+                {
+                    "keyId": "abc",
+                    "sig": "def"
+                }
+            ],
+            "yanked": false
+        }"#]];
+        let obj: super::CatalogRelease = serde_json::from_str(expect.data).unwrap();
+        assert_eq!(obj.extra.len(), 2);
+        let reserialized = pretty_json(obj).expect("serialization shouldn't fail");
+        expect.assert_eq(&reserialized);
+    }
+
+    #[test]
+    fn test_module_json_roundtrip_extra_fields() {
+        let expect = expect![[r#"
+        {
+            "catalogmodule.v1": {
+                "name": "warpsys.org/bash",
+                "releases": {},
+                "metadata": {},
+                "maintainers": [
+                    "someone@example.org"
+                ]
+            }
+        }"#]];
+        let obj: super::CatalogModuleCapsule = serde_json::from_str(expect.data).unwrap();
+        let reserialized = pretty_json(obj).expect("serialization shouldn't fail");
+        expect.assert_eq(&reserialized);
+    }
+
+    #[test]
+    fn test_module_json_roundtrip_unknown_version() {
+        let expect = expect![[r#"
+        {
+            "catalogmodule.v2": {
+                "name": "warpsys.org/bash",
+                "lineage": {
+                    "v5.1.16": null
+                }
+            }
+        }"#]];
+        let obj: super::CatalogModuleCapsule = serde_json::from_str(expect.data).unwrap();
+        assert_eq!(obj.version(), "catalogmodule.v2");
+        let reserialized = pretty_json(obj).expect("serialization shouldn't fail");
+        expect.assert_eq(&reserialized);
+    }
 }
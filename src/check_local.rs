@@ -0,0 +1,112 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::ErrorKind;
+use url::Url;
+
+use crate::wares::ResolvedWares;
+
+/// The result of `check-local`. Every local copy of a ware is listed by its path on disk;
+/// a ware with several local mirrors can appear in more than one list.
+#[derive(Debug, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LocalReport {
+    pub present: BTreeMap<String, Vec<String>>,
+    pub missing: BTreeMap<String, Vec<String>>,
+    pub zero_length: BTreeMap<String, Vec<String>>,
+    /// Local paths which could not be checked, with the reason.
+    pub errors: BTreeMap<String, Vec<String>>,
+}
+
+impl LocalReport {
+    pub fn is_ok(&self) -> bool {
+        self.missing.is_empty() && self.zero_length.is_empty() && self.errors.is_empty()
+    }
+}
+
+/// Checks every resolved `file://` URL, which is what `ca+file://` mirrors resolve to, for a
+/// non-empty file. URLs with any other scheme are ignored.
+pub fn check(wares: &ResolvedWares) -> LocalReport {
+    let mut report = LocalReport::default();
+    for (ware_id, urls) in wares.iter() {
+        for url in urls.iter() {
+            let path = match Url::parse(url) {
+                Ok(u) if u.scheme() == "file" => match u.to_file_path() {
+                    Ok(p) => p,
+                    Err(_) => {
+                        let entry = report.errors.entry(ware_id.clone()).or_default();
+                        entry.push(format!("{url}: not a local path"));
+                        continue;
+                    }
+                },
+                _ => continue,
+            };
+            let shown = path.to_string_lossy().into_owned();
+            let list = match fs::metadata(&path) {
+                Ok(m) if m.is_file() && m.len() > 0 => &mut report.present,
+                Ok(m) if m.is_file() => &mut report.zero_length,
+                Ok(_) => {
+                    let entry = report.errors.entry(ware_id.clone()).or_default();
+                    entry.push(format!("{shown}: not a file"));
+                    continue;
+                }
+                Err(e) if e.kind() == ErrorKind::NotFound => &mut report.missing,
+                Err(e) => {
+                    let entry = report.errors.entry(ware_id.clone()).or_default();
+                    entry.push(format!("{shown}: {e}"));
+                    continue;
+                }
+            };
+            list.entry(ware_id.clone()).or_default().push(shown);
+        }
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TempCatalog;
+    use crate::wares;
+    use serde_json::json;
+
+    #[test]
+    fn test_check() {
+        let store = TempCatalog::new();
+        store.write("abc/def/abcdefgh", "ware bytes");
+        store.write("emp/tyw/emptyware", "");
+        let catalog = TempCatalog::new();
+        catalog.add_module(
+            "example.org/foo",
+            &[(
+                "v1",
+                &[
+                    ("present", "tar:abcdefgh"),
+                    ("empty", "tar:emptyware"),
+                    ("missing", "tar:missing0"),
+                ],
+            )],
+        );
+        let mirror = Url::from_directory_path(&store.path).unwrap();
+        catalog.add_mirrors(
+            "example.org",
+            json!({"byModule": {"example.org/foo": {"tar": [
+                format!("ca+{mirror}"),
+                "ca+https://mirror.example.org",
+            ]}}}),
+        );
+
+        let resolved =
+            wares::resolve_all(&catalog.path, &Default::default(), &Default::default()).unwrap();
+        let report = check(&resolved);
+        let path = |rel: &str| vec![store.path.join(rel).to_string_lossy().into_owned()];
+        assert_eq!(report.present["tar:abcdefgh"], path("abc/def/abcdefgh"));
+        assert_eq!(
+            report.zero_length["tar:emptyware"],
+            path("emp/tyw/emptyware")
+        );
+        assert_eq!(report.missing["tar:missing0"], path("mis/sin/missing0"));
+        assert!(report.errors.is_empty());
+        assert!(!report.is_ok());
+    }
+}
@@ -0,0 +1,260 @@
+use rayon::prelude::*;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::time::Duration;
+use url::Url;
+
+use crate::wares::ResolvedWares;
+
+const MAX_REDIRECTS: usize = 5;
+
+/// How `check-mirrors` probes URLs.
+pub struct ProbeOptions {
+    /// Number of URLs probed at the same time.
+    pub concurrency: usize,
+    pub timeout: Duration,
+    /// How many more times a URL is probed after a network error or a 5xx response.
+    pub retries: usize,
+}
+
+/// The result of probing one URL.
+#[derive(Debug, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Probe {
+    pub url: String,
+    /// True if the URL did not answer with a 2xx status, after following redirects.
+    pub dead: bool,
+    /// The method of the request which decided the result, "HEAD" or "GET".
+    pub method: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<u16>,
+    /// Every location the URL redirected to, in order.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub redirects: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content_length: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// The result of `check-mirrors`.
+#[derive(Debug, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MirrorReport {
+    /// Every dead URL, so broken mirrors can be spotted without reading the whole report.
+    pub dead: Vec<String>,
+    /// URLs which are not http or https and were not probed.
+    pub skipped: Vec<String>,
+    pub wares: BTreeMap<String, Vec<Probe>>,
+}
+
+// sends a single request without following redirects. Any response is returned as Ok, whatever
+// its status; only network errors are returned as Err.
+fn request(agent: &ureq::Agent, method: &str, url: &str) -> Result<ureq::Response, String> {
+    let mut req = agent.request(method, url);
+    if method == "GET" {
+        req = req.set("Range", "bytes=0-0");
+    }
+    match req.call() {
+        Ok(response) | Err(ureq::Error::Status(_, response)) => Ok(response),
+        Err(ureq::Error::Transport(t)) => Err(match t.message() {
+            Some(message) => format!("{}: {message}", t.kind()),
+            None => t.kind().to_string(),
+        }),
+    }
+}
+
+// the full size of the resource: a ranged GET answers with "Content-Range: bytes 0-0/<size>".
+fn content_length(method: &str, response: &ureq::Response) -> Option<u64> {
+    if method == "GET" && response.status() == 206 {
+        let range = response.header("Content-Range")?;
+        return range.rsplit('/').next()?.parse().ok();
+    }
+    response.header("Content-Length")?.parse().ok()
+}
+
+fn probe_with(agent: &ureq::Agent, method: &'static str, url: &str) -> Probe {
+    let mut probe = Probe {
+        url: url.to_string(),
+        method,
+        ..Default::default()
+    };
+    let mut current = url.to_string();
+    loop {
+        let response = match request(agent, method, &current) {
+            Ok(r) => r,
+            Err(e) => {
+                probe.dead = true;
+                probe.error = Some(e);
+                return probe;
+            }
+        };
+        let status = response.status();
+        probe.status = Some(status);
+        if (300..400).contains(&status) {
+            let next = response
+                .header("Location")
+                .and_then(|l| Url::parse(&current).ok()?.join(l).ok());
+            match next {
+                Some(next) if probe.redirects.len() < MAX_REDIRECTS => {
+                    current = next.to_string();
+                    probe.redirects.push(current.clone());
+                    continue;
+                }
+                Some(_) => probe.error = Some(String::from("too many redirects")),
+                None => probe.error = Some(String::from("redirect without a location")),
+            }
+            probe.dead = true;
+            return probe;
+        }
+        probe.dead = !(200..300).contains(&status);
+        if !probe.dead {
+            probe.content_length = content_length(method, &response);
+        }
+        return probe;
+    }
+}
+
+// a failure which may go away when asked again.
+fn is_transient(probe: &Probe) -> bool {
+    match probe.status {
+        None => true,
+        Some(status) => status >= 500,
+    }
+}
+
+/// Probes `url` with HEAD, falling back to a GET of the first byte for servers which do not
+/// answer HEAD requests successfully. Network errors and 5xx responses are retried.
+pub fn probe(agent: &ureq::Agent, url: &str, retries: usize) -> Probe {
+    let mut result = Probe::default();
+    for _ in 0..=retries {
+        result = probe_with(agent, "HEAD", url);
+        if result.dead && result.status.is_some() {
+            result = probe_with(agent, "GET", url);
+        }
+        if !result.dead || !is_transient(&result) {
+            break;
+        }
+    }
+    result
+}
+
+/// Probes every http and https URL of every ware.
+pub fn check(
+    wares: &ResolvedWares,
+    options: &ProbeOptions,
+) -> Result<MirrorReport, Box<dyn Error>> {
+    let agent = ureq::AgentBuilder::new()
+        .timeout(options.timeout)
+        .redirects(0)
+        .build();
+    let mut report = MirrorReport::default();
+    let mut targets = Vec::new();
+    for (ware_id, urls) in wares.iter() {
+        for url in urls.iter() {
+            match Url::parse(url) {
+                Ok(u) if u.scheme() == "http" || u.scheme() == "https" => {
+                    targets.push((ware_id, url))
+                }
+                _ => report.skipped.push(url.clone()),
+            }
+        }
+    }
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(options.concurrency.max(1))
+        .build()?;
+    let probes: Vec<Probe> = pool.install(|| {
+        targets
+            .par_iter()
+            .map(|(_, url)| probe(&agent, url, options.retries))
+            .collect()
+    });
+    for ((ware_id, _), probe) in targets.into_iter().zip(probes) {
+        if probe.dead {
+            report.dead.push(probe.url.clone());
+        }
+        report.wares.entry(ware_id.clone()).or_default().push(probe);
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::StandIn;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tiny_http::{Header, Method, Response};
+
+    fn stand_in() -> StandIn {
+        let flaky = AtomicUsize::new(0);
+        StandIn::new(move |request| {
+            let header = |k: &str, v: &str| Header::from_bytes(k, v).unwrap();
+            let head = *request.method() == Method::Head;
+            match request.url() {
+                "/ok" => Response::from_string("0123456789").boxed(),
+                "/moved" => Response::empty(302)
+                    .with_header(header("Location", "/ok"))
+                    .boxed(),
+                "/no-head" if head => Response::empty(405).boxed(),
+                "/no-head" => Response::from_string("0")
+                    .with_status_code(206)
+                    .with_header(header("Content-Range", "bytes 0-0/42"))
+                    .boxed(),
+                "/flaky" if flaky.fetch_add(1, Ordering::SeqCst) < 2 => {
+                    Response::empty(503).boxed()
+                }
+                "/flaky" => Response::from_string("ok").boxed(),
+                _ => Response::empty(404).boxed(),
+            }
+        })
+    }
+
+    #[test]
+    fn test_check() {
+        let server = stand_in();
+        let url = |p: &str| format!("{}{p}", server.url);
+        let mut wares = BTreeMap::new();
+        wares.insert(
+            String::from("tar:abcdefgh"),
+            vec![url("/gone"), url("/moved"), url("/ok")],
+        );
+        wares.insert(
+            String::from("tar:ijklmnop"),
+            vec![url("/no-head"), String::from("ftp://example.org/x")],
+        );
+        let options = ProbeOptions {
+            concurrency: 2,
+            timeout: Duration::from_secs(5),
+            retries: 0,
+        };
+        let report = check(&wares, &options).unwrap();
+        assert_eq!(report.dead, vec![url("/gone")]);
+        assert_eq!(report.skipped, vec!["ftp://example.org/x"]);
+
+        let probes = &report.wares["tar:abcdefgh"];
+        assert_eq!(probes[0].status, Some(404));
+        assert_eq!(probes[1].redirects, vec![url("/ok")]);
+        assert_eq!(probes[1].content_length, Some(10));
+        assert_eq!(probes[2].method, "HEAD");
+        assert_eq!(probes[2].content_length, Some(10));
+
+        let probe = &report.wares["tar:ijklmnop"][0];
+        assert_eq!((probe.method, probe.dead), ("GET", false));
+        assert_eq!(probe.content_length, Some(42));
+    }
+
+    #[test]
+    fn test_retry() {
+        let server = stand_in();
+        let agent = ureq::AgentBuilder::new().redirects(0).build();
+        let url = format!("{}/flaky", server.url);
+        // each attempt sends a HEAD and a GET, the first attempt uses up both failures.
+        assert!(probe(&agent, &url, 0).dead);
+        let server = stand_in();
+        let url = format!("{}/flaky", server.url);
+        let result = probe(&agent, &url, 1);
+        assert!(!result.dead);
+        assert_eq!(result.status, Some(200));
+    }
+}
@@ -0,0 +1,48 @@
+use indexmap::IndexMap;
+use serde::Deserialize;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use crate::rewrite::RuleConfig;
+use crate::str_error::StrError; //required by errf macro
+
+/// Local settings which are not part of the catalog, read from the file given with `--config`.
+///
+/// ```json
+/// {
+///   "rewrites": [
+///     {"prefix": "https://ftp.gnu.org/", "replacement": "https://proxy.internal/gnu/"}
+///   ],
+///   "priorities": {"ca+https://cdn.internal/": 10},
+///   "caLayouts": {"ca+https://sharded.internal": "packtype/ab/cd"}
+/// }
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct Config {
+    /// URL rewrite rules, applied after any given with `--rewrite`.
+    #[serde(default)]
+    pub rewrites: Vec<RuleConfig>,
+    /// The priority of mirrors, keyed by mirror URL as written in `_mirrors.json` or a prefix of
+    /// it; the longest prefix wins. Locations from mirrors with a higher priority are listed
+    /// first. The default is 0.
+    #[serde(default)]
+    pub priorities: IndexMap<String, i64>,
+    /// The layout of content-addressable mirrors which do not use the default `abc/def` layout,
+    /// keyed by mirror URL. See `ca_layout::Layout` for the syntax.
+    #[serde(default)]
+    pub ca_layouts: IndexMap<String, String>,
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Config, Box<dyn Error>> {
+        let contents = fs::read_to_string(path).map_err(|e| errf!("{}: {e}", path.display()))?;
+        let de = &mut serde_json::Deserializer::from_str(&contents);
+        let config = serde_path_to_error::deserialize(de).map_err(|e| {
+            let p = e.path().to_string();
+            errf!("{}: {p}: {}", path.display(), e.into_inner())
+        })?;
+        Ok(config)
+    }
+}
@@ -0,0 +1,159 @@
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::catalog::Catalog;
+use crate::catalog_mirrors::CatalogMirrorsCapsule;
+use crate::wares::ResolvedWares;
+
+/// Gaps in the mirrors of one module.
+#[derive(Debug, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ModuleCoverage {
+    /// Released wares which no mirror resolves to a location, with the items using them
+    /// as "release:item".
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub wares_without_mirrors: BTreeMap<String, Vec<String>>,
+    /// Packtypes used by a release of the module which have no byModule mirror.
+    #[serde(skip_serializing_if = "BTreeSet::is_empty")]
+    pub packtypes_without_mirrors: BTreeSet<String>,
+}
+
+impl ModuleCoverage {
+    fn is_empty(&self) -> bool {
+        self.wares_without_mirrors.is_empty() && self.packtypes_without_mirrors.is_empty()
+    }
+}
+
+/// The result of `coverage`. Only modules with gaps are listed.
+#[derive(Debug, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CoverageReport {
+    pub modules: BTreeMap<String, ModuleCoverage>,
+    /// byWare entries for wares which no release references, with their mirrors.
+    pub unreferenced_wares: BTreeMap<String, Vec<String>>,
+}
+
+/// Compares the wares released in `catalog` with the mirrors which serve them.
+/// `resolved` is the result of resolving the catalog's wares.
+pub fn coverage(catalog: &Catalog, resolved: &ResolvedWares) -> CoverageReport {
+    let mut report = CoverageReport::default();
+    let mirrors = match &catalog.mirrors {
+        CatalogMirrorsCapsule::V1(m) => Some(m),
+        CatalogMirrorsCapsule::Unknown { .. } => None,
+    };
+    for m in catalog.modules.iter() {
+        let name = &m.module.name;
+        let by_pack_type = mirrors.and_then(|mirrors| mirrors.by_module.get(name));
+        let mut module = ModuleCoverage::default();
+        for release in m.releases.iter() {
+            for (item, ware_id) in release.items.iter() {
+                if resolved.get(ware_id).is_none_or(|urls| urls.is_empty()) {
+                    let items = module
+                        .wares_without_mirrors
+                        .entry(ware_id.clone())
+                        .or_default();
+                    items.push(format!("{}:{item}", release.name));
+                }
+                // a ware ID without packtype has no mirrors above, and is reported by `validate`.
+                let Some((pack_type, _)) = ware_id.split_once(':') else {
+                    continue;
+                };
+                let has_mirrors = by_pack_type
+                    .and_then(|p| p.get(pack_type))
+                    .is_some_and(|urls| !urls.is_empty());
+                if !has_mirrors {
+                    module
+                        .packtypes_without_mirrors
+                        .insert(pack_type.to_string());
+                }
+            }
+        }
+        for items in module.wares_without_mirrors.values_mut() {
+            items.sort();
+        }
+        if !module.is_empty() {
+            report.modules.insert(name.clone(), module);
+        }
+    }
+    let referenced: BTreeSet<&String> = catalog.refs.values().collect();
+    for (ware_id, urls) in mirrors.iter().flat_map(|m| m.by_ware.iter()) {
+        if !referenced.contains(ware_id) {
+            let urls = urls.iter().cloned().collect();
+            report.unreferenced_wares.insert(ware_id.clone(), urls);
+        }
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TempCatalog;
+    use expect_test::expect;
+    use serde_json::json;
+
+    #[test]
+    fn test_coverage() {
+        let catalog = TempCatalog::new();
+        catalog.add_module(
+            "example.org/foo",
+            &[
+                ("v1", &[("src", "tar:abcdefgh"), ("repo", "git:0123456789")]),
+                ("v2", &[("src", "tar:abcdefgh")]),
+            ],
+        );
+        catalog.add_module(
+            "example.org/bar",
+            &[("v1", &[("src", "tar:ijklmnop"), ("bin", "nopacktype")])],
+        );
+        catalog.add_mirrors(
+            "example.org",
+            json!({
+                "byWare": {
+                    "tar:unused00": ["https://example.org/unused.tgz"],
+                    "unused01": ["https://example.org/unused01.tgz"]
+                },
+                "byModule": {"example.org/bar": {"tar": ["ca+https://mirror.example.org"]}}
+            }),
+        );
+        let loaded = Catalog::load(&catalog.path, &Default::default()).unwrap();
+        let resolved = loaded.wares(&Default::default()).unwrap();
+        let report = coverage(&loaded, &resolved);
+        expect![[r#"
+            {
+              "modules": {
+                "example.org/bar": {
+                  "waresWithoutMirrors": {
+                    "nopacktype": [
+                      "v1:bin"
+                    ]
+                  }
+                },
+                "example.org/foo": {
+                  "waresWithoutMirrors": {
+                    "git:0123456789": [
+                      "v1:repo"
+                    ],
+                    "tar:abcdefgh": [
+                      "v1:src",
+                      "v2:src"
+                    ]
+                  },
+                  "packtypesWithoutMirrors": [
+                    "git",
+                    "tar"
+                  ]
+                }
+              },
+              "unreferencedWares": {
+                "tar:unused00": [
+                  "https://example.org/unused.tgz"
+                ],
+                "unused01": [
+                  "https://example.org/unused01.tgz"
+                ]
+              }
+            }"#]]
+        .assert_eq(&serde_json::to_string_pretty(&report).unwrap());
+    }
+}
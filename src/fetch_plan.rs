@@ -0,0 +1,228 @@
+use clap::ValueEnum;
+use std::fmt::Write;
+
+use crate::wares::ResolvedWares;
+
+/// The download tool a plan is written for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    /// A POSIX shell script using curl, trying each mirror in turn
+    Sh,
+    /// An aria2c input file (`aria2c -i`); mirrors of a ware are downloaded as one file
+    Aria2,
+    /// A curl config file (`curl -K`, curl 8.10 or later); later mirrors of a ware are only
+    /// downloaded if the earlier ones failed
+    CurlConfig,
+    /// A POSIX shell script using wget, trying each mirror in turn
+    WgetList,
+}
+
+/// One file to download: every URL is a mirror of the same ware.
+#[derive(Debug, PartialEq)]
+pub struct PlanEntry {
+    pub ware_id: String,
+    pub file_name: String,
+    pub urls: Vec<String>,
+}
+
+/// The output file name for a ware, e.g. "tar-5K7rek..." for "tar:5K7rek...".
+pub fn file_name(ware_id: &str) -> String {
+    ware_id.replace([':', '/'], "-")
+}
+
+/// Builds a download plan from resolved wares.
+/// Git wares cannot be downloaded as a file and wares without mirrors cannot be downloaded at all;
+/// both are skipped with a warning.
+pub fn plan(wares: &ResolvedWares) -> Vec<PlanEntry> {
+    let mut result = Vec::new();
+    for (ware_id, urls) in wares.iter() {
+        if ware_id.starts_with("git:") {
+            eprintln!("WARNING: skipping git ware {ware_id}, it must be cloned");
+            continue;
+        }
+        if urls.is_empty() {
+            eprintln!("WARNING: skipping {ware_id}, it has no mirrors");
+            continue;
+        }
+        result.push(PlanEntry {
+            ware_id: ware_id.clone(),
+            file_name: file_name(ware_id),
+            urls: urls.clone(),
+        });
+    }
+    result
+}
+
+fn sh_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', r#"'\''"#))
+}
+
+fn config_quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', r"\\").replace('"', "\\\""))
+}
+
+// `{download}` is replaced with a command writing "$url" to "$out.part".
+const SH_HEADER: &str = r#"#!/bin/sh
+# Generated by catalog-manifest fetch-plan.
+# Downloads every ware into the current directory, trying each mirror in turn.
+set -u
+failed=0
+fetch() {
+	out="$1"
+	shift
+	[ -s "$out" ] && return 0
+	for url in "$@"; do
+		if {download}; then
+			mv "$out.part" "$out"
+			return 0
+		fi
+	done
+	rm -f "$out.part"
+	echo "failed to fetch $out" >&2
+	failed=1
+}
+"#;
+
+const CURL_DOWNLOAD: &str = r#"curl -fsSL -o "$out.part" "$url""#;
+const WGET_DOWNLOAD: &str = r#"wget -q -O "$out.part" "$url""#;
+
+fn render_sh(out: &mut String, entries: &[PlanEntry], download: &str) {
+    out.push_str(&SH_HEADER.replace("{download}", download));
+    for e in entries {
+        let urls: Vec<String> = e.urls.iter().map(|u| sh_quote(u)).collect();
+        writeln!(out, "fetch {} {}", sh_quote(&e.file_name), urls.join(" ")).unwrap();
+    }
+    out.push_str("exit $failed\n");
+}
+
+/// Writes a plan in the given format.
+pub fn render(entries: &[PlanEntry], format: Format) -> String {
+    let mut out = String::new();
+    match format {
+        Format::Sh => render_sh(&mut out, entries, CURL_DOWNLOAD),
+        Format::Aria2 => {
+            for e in entries {
+                writeln!(out, "{}", e.urls.join("\t")).unwrap();
+                writeln!(out, "  out={}", e.file_name).unwrap();
+            }
+        }
+        Format::CurlConfig => {
+            // one transfer per mirror: a failed one leaves no file behind, and once a mirror
+            // succeeded the transfers of the remaining ones are skipped.
+            for (i, e) in entries.iter().enumerate() {
+                for (j, url) in e.urls.iter().enumerate() {
+                    if i > 0 || j > 0 {
+                        out.push_str("next\n");
+                    }
+                    if j == 0 {
+                        writeln!(out, "# {}", e.ware_id).unwrap();
+                    }
+                    writeln!(out, "url = {}", config_quote(url)).unwrap();
+                    writeln!(out, "output = {}", config_quote(&e.file_name)).unwrap();
+                    out.push_str("fail\nlocation\nremove-on-error\nskip-existing\n");
+                }
+            }
+        }
+        Format::WgetList => render_sh(&mut out, entries, WGET_DOWNLOAD),
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use expect_test::expect;
+    use std::collections::BTreeMap;
+
+    fn entries() -> Vec<PlanEntry> {
+        let mut wares = BTreeMap::new();
+        wares.insert(
+            String::from("tar:abcdefgh"),
+            vec![
+                String::from("https://a.example.org/abc/def/abcdefgh"),
+                String::from("https://b.example.org/it's.tar.gz"),
+            ],
+        );
+        wares.insert(String::from("tar:nomirrors"), Vec::new());
+        wares.insert(
+            String::from("git:0123456789abcdef0123456789abcdef01234567"),
+            vec![String::from("https://github.com/warptools/warpforge")],
+        );
+        plan(&wares)
+    }
+
+    #[test]
+    fn test_plan() {
+        let entries = entries();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].file_name, "tar-abcdefgh");
+    }
+
+    #[test]
+    fn test_render_sh() {
+        let out = render(&entries(), Format::Sh);
+        let header = SH_HEADER.replace("{download}", CURL_DOWNLOAD);
+        assert!(out.starts_with(&header));
+        expect![[r#"
+            fetch 'tar-abcdefgh' 'https://a.example.org/abc/def/abcdefgh' 'https://b.example.org/it'\''s.tar.gz'
+            exit $failed
+        "#]]
+        .assert_eq(&out[header.len()..]);
+    }
+
+    #[test]
+    fn test_render_aria2() {
+        expect![[r#"
+            https://a.example.org/abc/def/abcdefgh	https://b.example.org/it's.tar.gz
+              out=tar-abcdefgh
+        "#]]
+        .assert_eq(&render(&entries(), Format::Aria2));
+    }
+
+    #[test]
+    fn test_render_curl_config() {
+        let mut entries = entries();
+        entries.push(PlanEntry {
+            ware_id: String::from("tar:ijklmnop"),
+            file_name: String::from("tar-ijklmnop"),
+            urls: vec![String::from("https://a.example.org/ijk/lmn/ijklmnop")],
+        });
+        expect![[r#"
+            # tar:abcdefgh
+            url = "https://a.example.org/abc/def/abcdefgh"
+            output = "tar-abcdefgh"
+            fail
+            location
+            remove-on-error
+            skip-existing
+            next
+            url = "https://b.example.org/it's.tar.gz"
+            output = "tar-abcdefgh"
+            fail
+            location
+            remove-on-error
+            skip-existing
+            next
+            # tar:ijklmnop
+            url = "https://a.example.org/ijk/lmn/ijklmnop"
+            output = "tar-ijklmnop"
+            fail
+            location
+            remove-on-error
+            skip-existing
+        "#]]
+        .assert_eq(&render(&entries, Format::CurlConfig));
+    }
+
+    #[test]
+    fn test_render_wget_list() {
+        let out = render(&entries(), Format::WgetList);
+        let header = SH_HEADER.replace("{download}", WGET_DOWNLOAD);
+        assert!(out.starts_with(&header));
+        expect![[r#"
+            fetch 'tar-abcdefgh' 'https://a.example.org/abc/def/abcdefgh' 'https://b.example.org/it'\''s.tar.gz'
+            exit $failed
+        "#]]
+        .assert_eq(&out[header.len()..]);
+    }
+}
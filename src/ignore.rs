@@ -0,0 +1,179 @@
+use regex::Regex;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::str_error::StrError; //required by errf macro
+
+/// The file in the catalog root listing paths the walkers skip.
+pub const IGNORE_FILE_NAME: &str = ".catalogignore";
+
+// version control directories which are not hidden.
+const VCS_DIRS: &[&str] = &["CVS", "_darcs"];
+
+struct Rule {
+    regex: Regex,
+    negated: bool,
+    dir_only: bool,
+}
+
+/// Decides which paths below the catalog root are skipped by the walkers.
+/// Hidden directories, such as `.git`, VCS directories and editor backup directories ending
+/// in `~` are skipped by default. The patterns of `.catalogignore` come after these defaults
+/// and use gitignore syntax, so `!.well-known/` includes a directory again.
+#[derive(Default)]
+pub struct Ignore {
+    root: PathBuf,
+    rules: Vec<Rule>,
+}
+
+impl Ignore {
+    /// Reads `.catalogignore` from the catalog `root`, if there is one.
+    pub fn load(root: &Path) -> Result<Ignore, Box<dyn Error>> {
+        let path = root.join(IGNORE_FILE_NAME);
+        let contents = match fs::read_to_string(&path) {
+            Ok(c) => c,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(errf!("{}: {e}", path.display())),
+        };
+        let ignore =
+            Ignore::parse(root, &contents).map_err(|e| errf!("{}: {e}", path.display()))?;
+        Ok(ignore)
+    }
+
+    /// Parses gitignore-style `patterns` for the catalog at `root`.
+    pub fn parse(root: &Path, patterns: &str) -> Result<Ignore, Box<dyn Error>> {
+        let mut rules = Vec::new();
+        for (i, line) in patterns.lines().enumerate() {
+            let line = line.trim_end();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (negated, pattern) = match line.strip_prefix('!') {
+                Some(p) => (true, p),
+                None => (false, line.strip_prefix('\\').unwrap_or(line)),
+            };
+            let (dir_only, pattern) = match pattern.strip_suffix('/') {
+                Some(p) => (true, p),
+                None => (false, pattern),
+            };
+            let regex =
+                Regex::new(&pattern_regex(pattern)).map_err(|e| errf!("line {}: {e}", i + 1))?;
+            rules.push(Rule {
+                regex,
+                negated,
+                dir_only,
+            });
+        }
+        Ok(Ignore {
+            root: root.to_path_buf(),
+            rules,
+        })
+    }
+
+    /// Reports whether the walkers skip `path`, which is below the catalog root.
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        let mut ignored =
+            is_dir && (name.starts_with('.') || name.ends_with('~') || VCS_DIRS.contains(&&*name));
+        let relative = path.strip_prefix(&self.root).unwrap_or(path);
+        let relative: Vec<_> = relative
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect();
+        let relative = relative.join("/");
+        // the last matching rule wins.
+        for rule in self.rules.iter() {
+            if (is_dir || !rule.dir_only) && rule.regex.is_match(&relative) {
+                ignored = !rule.negated;
+            }
+        }
+        ignored
+    }
+
+    /// Reports whether `path`, which need not exist any more, is skipped itself or lies in a
+    /// skipped directory. Paths outside the catalog root are never skipped.
+    pub fn skips(&self, path: &Path) -> bool {
+        let Ok(relative) = path.strip_prefix(&self.root) else {
+            return false;
+        };
+        let mut dir = self.root.clone();
+        let mut components = relative.components().peekable();
+        while let Some(component) = components.next() {
+            dir.push(component);
+            if components.peek().is_none() {
+                return self.is_ignored(&dir, dir.is_dir());
+            }
+            if self.is_ignored(&dir, true) {
+                return true;
+            }
+        }
+        false
+    }
+}
+
+// patterns containing a slash are relative to the root, others match a name at any depth.
+fn pattern_regex(pattern: &str) -> String {
+    let anchored = pattern.contains('/');
+    let pattern = pattern.strip_prefix('/').unwrap_or(pattern);
+    let mut result = String::from(if anchored { "^" } else { "^(?:.*/)?" });
+    let mut rest = pattern;
+    while let Some(c) = rest.chars().next() {
+        if let Some(r) = rest.strip_prefix("**/") {
+            result.push_str("(?:.*/)?");
+            rest = r;
+            continue;
+        }
+        if let Some(r) = rest.strip_prefix("**") {
+            result.push_str(".*");
+            rest = r;
+            continue;
+        }
+        match c {
+            '*' => result.push_str("[^/]*"),
+            '?' => result.push_str("[^/]"),
+            c => result.push_str(&regex::escape(&c.to_string())),
+        }
+        rest = &rest[c.len_utf8()..];
+    }
+    result.push('$');
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_ignored() {
+        let root = Path::new("/catalog");
+        let ignore = Ignore::parse(
+            root,
+            "# drafts are not published\ndrafts/\n/example.org/old*\n**/tmp/*.json\n!.well-known/\n",
+        )
+        .unwrap();
+        let cases = [
+            ("warpsys.org/bash", true, false),
+            (".git", true, true),
+            ("warpsys.org/.git", true, true),
+            ("warpsys.org/CVS", true, true),
+            ("warpsys.org/bash~", true, true),
+            (".gitignore", false, false),
+            ("drafts", true, true),
+            ("warpsys.org/drafts", true, true),
+            ("drafts", false, false),
+            ("example.org/old-foo", true, true),
+            ("warpsys.org/example.org/old-foo", true, false),
+            ("a/b/tmp/_mirrors.json", false, true),
+            ("tmp/_mirrors.json", false, true),
+            (".well-known", true, false),
+        ];
+        for (path, is_dir, expected) in cases {
+            assert_eq!(
+                ignore.is_ignored(&root.join(path), is_dir),
+                expected,
+                "{path}"
+            );
+        }
+    }
+}
@@ -1 +1,36 @@
+#[macro_use]
+mod macros;
 
+pub mod ca_layout;
+pub mod cache;
+pub mod catalog;
+pub mod catalog_errors;
+pub mod catalog_mirrors;
+pub mod catalog_releases;
+pub mod check_local;
+pub mod check_mirrors;
+pub mod config;
+pub mod coverage;
+pub mod fetch_plan;
+pub mod ignore;
+pub mod migrate;
+pub mod mirrors;
+pub mod modules;
+pub mod packtype;
+pub mod policy;
+pub mod releases;
+pub mod rewrite;
+pub mod schema;
+pub mod search;
+pub mod serve;
+pub mod show;
+pub mod stats;
+pub mod str_error;
+pub mod sync;
+#[cfg(test)]
+mod testutil;
+pub mod validate;
+pub mod walk;
+pub mod ware_id;
+pub mod wares;
+pub mod watch;
@@ -1,16 +1,24 @@
 use clap::{Parser, Subcommand};
 use std::error::Error;
 use std::path::PathBuf;
+use std::time::Duration;
 
-#[macro_use]
-mod macros;
+use catalog_manifest::errf;
+use catalog_manifest::str_error::StrError; //required by errf macro
+use catalog_manifest::{
+    catalog, check_local, check_mirrors, coverage, fetch_plan, migrate, mirrors, modules, releases,
+    search, serve, show, stats, sync, validate, wares, watch,
+};
 
-mod catalog_mirrors;
-mod catalog_releases;
-mod mirrors;
-mod releases;
-mod str_error;
-mod wares;
+use catalog_manifest::cache::Cache;
+use catalog_manifest::config::Config;
+use catalog_manifest::ignore::Ignore;
+use catalog_manifest::packtype::{GitResolver, Resolvers};
+use catalog_manifest::policy::Policy;
+use catalog_manifest::rewrite::{Rewrites, Rule};
+use catalog_manifest::schema::Schema;
+use catalog_manifest::walk::WalkOptions;
+use catalog_manifest::wares::{ResolveOptions, ResolvedWares};
 
 #[derive(Parser)]
 #[command(name = "catalog-manifest")]
@@ -21,6 +29,44 @@ struct Cli {
     #[arg(short, long, value_name = "DIRECTORY")]
     catalog_path: String,
 
+    /// How strictly catalog files are checked. Strict mode rejects unknown fields and capsule versions.
+    #[arg(long, value_enum, default_value_t = Schema::Lenient)]
+    schema: Schema,
+
+    /// Number of threads used to walk the catalog. Defaults to the number of CPUs.
+    #[arg(short, long, value_name = "N")]
+    jobs: Option<usize>,
+
+    /// Ignore the catalog's index cache, if there is one, and read every file.
+    #[arg(long)]
+    no_cache: bool,
+
+    /// Descend into symlinked directories and read symlinked files. Symlinks which lead back
+    /// into a directory being walked are skipped.
+    #[arg(long)]
+    follow_symlinks: bool,
+
+    /// Keep running and print the output again whenever the catalog changes.
+    #[arg(short, long)]
+    watch: bool,
+
+    /// With --watch, print only the difference to the previous output after the first run.
+    #[arg(long, requires = "watch")]
+    diff: bool,
+
+    /// A JSON file with local settings such as URL rewrite rules.
+    #[arg(long, value_name = "FILE")]
+    config: Option<PathBuf>,
+
+    /// Rewrite resolved URLs starting with FROM to start with TO instead; "re:FROM" is a regex.
+    /// May be repeated. The first matching rule wins; these come before rules from --config.
+    #[arg(long, value_name = "FROM=TO")]
+    rewrite: Vec<String>,
+
+    /// Print every URL rewrite and the rule which caused it to stderr.
+    #[arg(long)]
+    show_rewrites: bool,
+
     #[command(subcommand)]
     command: Commands,
 }
@@ -32,39 +78,484 @@ enum Commands {
     /// Print a unified mirrors JSON object
     Mirrors,
     ///Prints a list of ware IDs and fully qualified mirror locations.
-    Wares,
+    Wares {
+        /// Also list tarball URLs of git wares hosted on GitHub, GitLab or Codeberg
+        #[arg(long)]
+        forge_archives: bool,
+    },
+    /// Rewrites module and mirrors files to a different capsule version
+    Migrate {
+        /// The capsule version to migrate to, e.g. "v2"
+        #[arg(long, value_name = "VERSION")]
+        to: String,
+        /// Report which files would be migrated without writing them
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Print a download plan which fetches every ware from its mirrors
+    FetchPlan {
+        /// The download tool to write the plan for
+        #[arg(long, value_enum, default_value_t = fetch_plan::Format::Sh)]
+        format: fetch_plan::Format,
+    },
+    /// Report which wares are present, missing or empty on ca+file:// mirrors
+    CheckLocal,
+    /// Probe every mirror URL over HTTP and report dead links, redirects and sizes
+    CheckMirrors {
+        /// Number of URLs probed at the same time
+        #[arg(long, value_name = "N", default_value_t = 8)]
+        concurrency: usize,
+        /// Timeout for each request, in seconds
+        #[arg(long, value_name = "SECONDS", default_value_t = 30)]
+        timeout: u64,
+        /// How often a URL is probed again after a network error or a server error
+        #[arg(long, default_value_t = 2)]
+        retries: usize,
+    },
+    /// List released wares without mirrors, modules lacking mirrors for a packtype, and mirrored wares no release uses
+    Coverage,
+    /// List every module with its directory, number of releases and metadata
+    Modules {
+        /// Print module names as a tree grouped by their namespace segments
+        #[arg(long)]
+        tree: bool,
+    },
+    /// Find module names, release names, item names, ware IDs and metadata matching a pattern
+    Search {
+        pattern: String,
+        #[arg(long, value_enum, default_value_t = search::Mode::Substring)]
+        mode: search::Mode,
+    },
+    /// Show a module's metadata, releases, mirrors and the download URLs of its items
+    Show {
+        /// The module name, such as warpsys.org/bash
+        module: String,
+    },
+    /// Summarize the catalog: counts of modules, releases, items and wares, and how well wares are mirrored
+    Stats {
+        #[arg(long, value_enum, default_value_t = stats::Format::Json)]
+        format: stats::Format,
+    },
+    /// Check the catalog for problems, such as mirror URLs which violate the mirror policy
+    Validate {
+        /// A JSON file declaring allowed schemes, hosts and paths of mirror URLs.
+        /// Without one, only https mirrors are allowed
+        #[arg(long, value_name = "FILE")]
+        policy: Option<PathBuf>,
+        /// Rename modules whose name does not match their directory, then validate again
+        #[arg(long)]
+        fix: bool,
+    },
+    /// Download every ware into a local content-addressed store
+    Sync {
+        /// The directory to store wares in, using the same layout as content-addressable mirrors
+        #[arg(long, value_name = "DIR")]
+        dest: PathBuf,
+        /// How many times to try all mirrors of a ware before giving up
+        #[arg(long, default_value_t = 2)]
+        attempts: usize,
+        /// Timeout for each download, in seconds
+        #[arg(long, value_name = "SECONDS", default_value_t = 300)]
+        timeout: u64,
+    },
+    /// Serve the catalog as a read-only JSON API, reloading it when files change
+    Serve {
+        /// The address to listen on
+        #[arg(long, value_name = "ADDRESS", default_value = "127.0.0.1:8080")]
+        listen: String,
+    },
+    /// Manage the index cache stored in the catalog root
+    Cache {
+        #[command(subcommand)]
+        command: CacheCommands,
+    },
+}
+
+#[derive(Subcommand)]
+enum CacheCommands {
+    /// Parse the whole catalog and write the cache. Later runs only re-read changed files.
+    Build,
+    /// Check the cache against a full walk of the catalog
+    Verify,
+    /// Remove the cache
+    Clear,
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
     let args = Cli::parse();
-    match args.command {
-        Commands::Releases {} => cmd_releases(args)?,
-        Commands::Mirrors {} => cmd_mirrors(args)?,
-        Commands::Wares {} => cmd_wares(args)?,
+    if let Some(jobs) = args.jobs {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(jobs)
+            .build_global()?;
     }
+    let render = match args.command {
+        Commands::Releases => render_releases,
+        Commands::Mirrors => render_mirrors,
+        Commands::Wares { .. } => render_wares,
+        Commands::FetchPlan { .. } => render_fetch_plan,
+        Commands::Coverage => render_coverage,
+        Commands::Modules { .. } => render_modules,
+        Commands::Search { .. } => render_search,
+        Commands::Show { .. } => render_show,
+        Commands::Stats { .. } => render_stats,
+        _ if args.watch => return Err(errf!("--watch is not supported by this command")),
+        Commands::Migrate { .. } => return cmd_migrate(args),
+        Commands::Cache { .. } => return cmd_cache(args),
+        Commands::Serve { .. } => return cmd_serve(args),
+        Commands::Sync { .. } => return cmd_sync(args),
+        Commands::CheckLocal => return cmd_check_local(args),
+        Commands::Validate { .. } => return cmd_validate(args),
+        Commands::CheckMirrors { .. } => return cmd_check_mirrors(args),
+    };
+    if args.watch {
+        let dir = PathBuf::from(&args.catalog_path);
+        return watch::run(&dir, args.diff, || render(&args));
+    }
+    println!("{}", render(&args)?);
     Ok(())
 }
 
-fn cmd_releases(args: Cli) -> Result<(), Box<dyn Error>> {
-    let dir = PathBuf::from(args.catalog_path);
-    let result = releases::collect(&dir)?;
-    let output = serde_json::to_string_pretty(&result)?;
-    println!("{output}");
+fn walk_options(args: &Cli) -> Result<WalkOptions, Box<dyn Error>> {
+    let dir = PathBuf::from(&args.catalog_path);
+    let cache = if !args.no_cache && Cache::exists(&dir) {
+        Cache::open(&dir)?
+    } else {
+        Cache::default()
+    };
+    Ok(WalkOptions {
+        schema: args.schema,
+        cache,
+        ignore: Ignore::load(&dir)?,
+        follow_symlinks: args.follow_symlinks,
+    })
+}
+
+fn load_config(args: &Cli) -> Result<Config, Box<dyn Error>> {
+    match &args.config {
+        Some(path) => Config::load(path),
+        None => Ok(Config::default()),
+    }
+}
+
+fn rewrites(args: &Cli, config: &Config) -> Result<Rewrites, Box<dyn Error>> {
+    let mut rules = Vec::new();
+    for rule in args.rewrite.iter() {
+        rules.push(Rule::parse(rule)?);
+    }
+    for rule in config.rewrites.iter() {
+        rules.push(Rule::from_config(rule)?);
+    }
+    Ok(Rewrites {
+        rules,
+        trace: args.show_rewrites,
+    })
+}
+
+// resolves every ware with the priorities and layouts of the config file and applies the rewrite rules to
+// the resulting URLs.
+fn resolve_wares(
+    args: &Cli,
+    opts: &WalkOptions,
+    resolvers: Resolvers,
+) -> Result<ResolvedWares, Box<dyn Error>> {
+    let config = load_config(args)?;
+    let rewrites = rewrites(args, &config)?;
+    let resolve = ResolveOptions {
+        resolvers,
+        priorities: config.priorities,
+        layouts: config.ca_layouts,
+    };
+    let start_dir = PathBuf::from(&args.catalog_path);
+    let result = wares::resolve_all(&start_dir, opts, &resolve)?;
+    Ok(rewrites.apply(result))
+}
+
+fn render_releases(args: &Cli) -> Result<String, Box<dyn Error>> {
+    let opts = walk_options(args)?;
+    let dir = PathBuf::from(&args.catalog_path);
+    let result = releases::collect(&dir, &opts)?;
+    opts.cache.save()?;
+    Ok(serde_json::to_string_pretty(&result)?)
+}
+
+fn render_mirrors(args: &Cli) -> Result<String, Box<dyn Error>> {
+    let opts = walk_options(args)?;
+    let start_dir = PathBuf::from(&args.catalog_path);
+    let result = mirrors::collect(&start_dir, &opts)?;
+    opts.cache.save()?;
+    Ok(serde_json::to_string_pretty(&result)?)
+}
+
+fn render_wares(args: &Cli) -> Result<String, Box<dyn Error>> {
+    let Commands::Wares { forge_archives } = args.command else {
+        unreachable!()
+    };
+    let mut resolvers = Resolvers::default();
+    resolvers.register("git", GitResolver { forge_archives });
+    let opts = walk_options(args)?;
+    let result = resolve_wares(args, &opts, resolvers)?;
+    opts.cache.save()?;
+    Ok(serde_json::to_string_pretty(&result)?)
+}
+
+fn render_fetch_plan(args: &Cli) -> Result<String, Box<dyn Error>> {
+    let Commands::FetchPlan { format } = args.command else {
+        unreachable!()
+    };
+    let opts = walk_options(args)?;
+    let result = resolve_wares(args, &opts, Resolvers::default())?;
+    opts.cache.save()?;
+    let entries = fetch_plan::plan(&result);
+    Ok(fetch_plan::render(&entries, format).trim_end().to_string())
+}
+
+fn render_coverage(args: &Cli) -> Result<String, Box<dyn Error>> {
+    let opts = walk_options(args)?;
+    let start_dir = PathBuf::from(&args.catalog_path);
+    let catalog = catalog::Catalog::load(&start_dir, &opts)?;
+    opts.cache.save()?;
+    let resolved = catalog.wares(&ResolveOptions::default())?;
+    let report = coverage::coverage(&catalog, &resolved);
+    Ok(serde_json::to_string_pretty(&report)?)
+}
+
+fn render_modules(args: &Cli) -> Result<String, Box<dyn Error>> {
+    let Commands::Modules { tree } = args.command else {
+        unreachable!()
+    };
+    let opts = walk_options(args)?;
+    let start_dir = PathBuf::from(&args.catalog_path);
+    let catalog = catalog::Catalog::load(&start_dir, &opts)?;
+    opts.cache.save()?;
+    let result = modules::list(&start_dir, &catalog);
+    if tree {
+        Ok(modules::tree(&result).trim_end().to_string())
+    } else {
+        Ok(serde_json::to_string_pretty(&result)?)
+    }
+}
+
+fn render_search(args: &Cli) -> Result<String, Box<dyn Error>> {
+    let Commands::Search { pattern, mode } = &args.command else {
+        unreachable!()
+    };
+    let pattern = search::Pattern::new(*mode, pattern)?;
+    let opts = walk_options(args)?;
+    let start_dir = PathBuf::from(&args.catalog_path);
+    let catalog = catalog::Catalog::load(&start_dir, &opts)?;
+    opts.cache.save()?;
+    let result = search::search(&catalog, &pattern);
+    Ok(serde_json::to_string_pretty(&result)?)
+}
+
+fn render_show(args: &Cli) -> Result<String, Box<dyn Error>> {
+    let Commands::Show { module } = &args.command else {
+        unreachable!()
+    };
+    let opts = walk_options(args)?;
+    let start_dir = PathBuf::from(&args.catalog_path);
+    let catalog = catalog::Catalog::load(&start_dir, &opts)?;
+    let mirror_files = mirrors::collect_files(&start_dir, &opts)?;
+    opts.cache.save()?;
+    let Some(found) = catalog.module(module) else {
+        return Err(errf!("module {module} not found"));
+    };
+    let config = load_config(args)?;
+    let rewrites = rewrites(args, &config)?;
+    let resolve = ResolveOptions {
+        resolvers: Resolvers::default(),
+        priorities: config.priorities,
+        layouts: config.ca_layouts,
+    };
+    let resolved = rewrites.apply(catalog.wares(&resolve)?);
+    let detail = show::show(&start_dir, found, &mirror_files, &resolved);
+    Ok(serde_json::to_string_pretty(&detail)?)
+}
+
+fn render_stats(args: &Cli) -> Result<String, Box<dyn Error>> {
+    let Commands::Stats { format } = args.command else {
+        unreachable!()
+    };
+    let opts = walk_options(args)?;
+    let start_dir = PathBuf::from(&args.catalog_path);
+    let catalog = catalog::Catalog::load(&start_dir, &opts)?;
+    opts.cache.save()?;
+    let resolved = catalog.wares(&ResolveOptions::default())?;
+    let result = stats::stats(&catalog, &resolved);
+    match format {
+        stats::Format::Json => Ok(serde_json::to_string_pretty(&result)?),
+        stats::Format::Table => Ok(stats::table(&result).trim_end().to_string()),
+    }
+}
+
+fn cmd_migrate(args: Cli) -> Result<(), Box<dyn Error>> {
+    let Commands::Migrate { to, dry_run } = &args.command else {
+        unreachable!()
+    };
+    let opts = walk_options(&args)?;
+    let start_dir = PathBuf::from(&args.catalog_path);
+    for m in migrate::run(&start_dir, to, *dry_run, &opts)? {
+        println!("{}: {} -> {}", m.path.display(), m.from, m.to);
+    }
     Ok(())
 }
 
-fn cmd_mirrors(args: Cli) -> Result<(), Box<dyn Error>> {
-    let start_dir = PathBuf::from(args.catalog_path);
-    let result = mirrors::collect(&start_dir)?;
-    let output = serde_json::to_string_pretty(&result)?;
-    println!("{output}");
+fn cmd_cache(args: Cli) -> Result<(), Box<dyn Error>> {
+    let Commands::Cache { command } = &args.command else {
+        unreachable!()
+    };
+    let dir = PathBuf::from(&args.catalog_path);
+    // a full walk which records every file into an empty cache.
+    let walked = WalkOptions {
+        schema: args.schema,
+        cache: Cache::empty(&dir),
+        ignore: Ignore::load(&dir)?,
+        follow_symlinks: args.follow_symlinks,
+    };
+    match command {
+        CacheCommands::Build => {
+            releases::collect(&dir, &walked)?;
+            mirrors::collect(&dir, &walked)?;
+            walked.cache.save()?;
+        }
+        CacheCommands::Verify => {
+            if !Cache::exists(&dir) {
+                return Err(errf!("no cache found at {}", Cache::path(&dir).display()));
+            }
+            let cache = Cache::open(&dir)?;
+            releases::collect(&dir, &walked)?;
+            mirrors::collect(&dir, &walked)?;
+            let report = cache.verify(&walked.cache);
+            println!("{}", serde_json::to_string_pretty(&report)?);
+            if !report.is_ok() {
+                return Err(errf!("cache does not match the catalog"));
+            }
+        }
+        CacheCommands::Clear => {
+            if Cache::exists(&dir) {
+                std::fs::remove_file(Cache::path(&dir))?;
+            }
+        }
+    }
     Ok(())
 }
 
-fn cmd_wares(args: Cli) -> Result<(), Box<dyn Error>> {
-    let start_dir = PathBuf::from(args.catalog_path);
-    let result = wares::resolve_all(&start_dir)?;
-    let output = serde_json::to_string_pretty(&result)?;
-    println!("{output}");
+fn cmd_serve(args: Cli) -> Result<(), Box<dyn Error>> {
+    let Commands::Serve { listen } = &args.command else {
+        unreachable!()
+    };
+    let listen = listen.clone();
+    let dir = PathBuf::from(&args.catalog_path);
+    let root = dir.clone();
+    serve::run(&listen, &root, move || {
+        let opts = walk_options(&args)?;
+        let catalog = catalog::Catalog::load(&dir, &opts)?;
+        opts.cache.save()?;
+        let config = load_config(&args)?;
+        let rewrites = rewrites(&args, &config)?;
+        let resolve = ResolveOptions {
+            resolvers: Resolvers::default(),
+            priorities: config.priorities,
+            layouts: config.ca_layouts,
+        };
+        let mut state = serve::State::new(&dir, catalog, &resolve)?;
+        state.wares = rewrites.apply(state.wares);
+        Ok(state)
+    })
+}
+
+fn cmd_sync(args: Cli) -> Result<(), Box<dyn Error>> {
+    let Commands::Sync {
+        dest,
+        attempts,
+        timeout,
+    } = &args.command
+    else {
+        unreachable!()
+    };
+    let opts = walk_options(&args)?;
+    let result = resolve_wares(&args, &opts, Resolvers::default())?;
+    opts.cache.save()?;
+    let report = sync::sync(&result, dest, *attempts, Duration::from_secs(*timeout));
+    println!("{}", serde_json::to_string_pretty(&report)?);
+    if !report.failed.is_empty() {
+        return Err(errf!(
+            "{} wares could not be downloaded",
+            report.failed.len()
+        ));
+    }
+    Ok(())
+}
+
+fn cmd_check_local(args: Cli) -> Result<(), Box<dyn Error>> {
+    let opts = walk_options(&args)?;
+    let result = resolve_wares(&args, &opts, Resolvers::default())?;
+    opts.cache.save()?;
+    let report = check_local::check(&result);
+    println!("{}", serde_json::to_string_pretty(&report)?);
+    if !report.is_ok() {
+        return Err(errf!("some wares are missing from local mirrors"));
+    }
+    Ok(())
+}
+
+fn cmd_check_mirrors(args: Cli) -> Result<(), Box<dyn Error>> {
+    let Commands::CheckMirrors {
+        concurrency,
+        timeout,
+        retries,
+    } = &args.command
+    else {
+        unreachable!()
+    };
+    let opts = walk_options(&args)?;
+    let result = resolve_wares(&args, &opts, Resolvers::default())?;
+    opts.cache.save()?;
+    let options = check_mirrors::ProbeOptions {
+        concurrency: *concurrency,
+        timeout: Duration::from_secs(*timeout),
+        retries: *retries,
+    };
+    let report = check_mirrors::check(&result, &options)?;
+    println!("{}", serde_json::to_string_pretty(&report)?);
+    if !report.dead.is_empty() {
+        return Err(errf!("{} mirror URLs are dead", report.dead.len()));
+    }
+    Ok(())
+}
+
+fn cmd_validate(args: Cli) -> Result<(), Box<dyn Error>> {
+    let Commands::Validate { policy, fix } = &args.command else {
+        unreachable!()
+    };
+    let policy = match policy {
+        Some(path) => Policy::load(path)?,
+        None => Policy::default(),
+    };
+    let opts = walk_options(&args)?;
+    let start_dir = PathBuf::from(&args.catalog_path);
+    // modules are checked before their refs are joined, which fails on duplicates.
+    let mut modules = releases::collect_modules(&start_dir, &opts)?;
+    let mirror_files = mirrors::collect_files(&start_dir, &opts)?;
+    if *fix {
+        let renamed = validate::fix_module_names(&start_dir, &modules, &mirror_files)?;
+        for r in renamed.iter() {
+            println!(r#"fixed {}: "{}" -> "{}""#, r.path.display(), r.from, r.to);
+        }
+        if !renamed.is_empty() {
+            modules = releases::collect_modules(&start_dir, &opts)?;
+        }
+    }
+    let violations = validate::validate(&start_dir, &modules, &mirror_files, &policy);
+    opts.cache.save()?;
+    for v in violations.iter() {
+        println!("{v}");
+    }
+    if !violations.is_empty() {
+        return Err(errf!(
+            "validation failed with {} problem(s)",
+            violations.len()
+        ));
+    }
     Ok(())
 }
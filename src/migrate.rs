@@ -0,0 +1,230 @@
+use std::error::Error;
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde_json::Value;
+
+use crate::catalog_mirrors::CatalogMirrorsCapsule;
+use crate::catalog_releases::CatalogModuleCapsule;
+use crate::str_error::StrError; //required by errf macro
+use crate::walk::{self, WalkOptions};
+
+const MODULE_FILE_NAME: &str = "_module.json";
+const MIRRORS_FILE_NAME: &str = "_mirrors.json";
+
+/// A single step which rewrites the body of a capsule from one version to the next.
+/// `family` is the capsule name without its version, e.g. "catalogmodule".
+pub struct Migration {
+    pub family: &'static str,
+    pub from: &'static str,
+    pub to: &'static str,
+    pub apply: fn(Value) -> Result<Value, Box<dyn Error>>,
+}
+
+/// All known migrations. Steps are chained together to reach the requested version.
+/// There is currently only a single version of each capsule, so this list is empty and
+/// `run` fails with "no migration path" for any other version.
+pub const MIGRATIONS: &[Migration] = &[];
+
+#[derive(Debug, PartialEq)]
+pub struct Migrated {
+    pub path: PathBuf,
+    pub from: String,
+    pub to: String,
+}
+
+/// Finds the chain of migrations which takes a capsule of `family` from version `from` to `to`.
+pub fn plan<'a>(
+    migrations: &'a [Migration],
+    family: &str,
+    from: &str,
+    to: &str,
+) -> Result<Vec<&'a Migration>, Box<dyn Error>> {
+    let mut steps = Vec::new();
+    let mut current = from;
+    while current != to {
+        let step = migrations
+            .iter()
+            .find(|m| m.family == family && m.from == current)
+            .ok_or_else(|| -> Box<dyn Error> {
+                errf!("no migration path from {family}.{from} to {family}.{to}")
+            })?;
+        if steps.len() > migrations.len() {
+            return Err(errf!("migration cycle detected for {family}.{from}"));
+        }
+        steps.push(step);
+        current = step.to;
+    }
+    Ok(steps)
+}
+
+fn split_capsule(doc: &Value) -> Result<(String, String, Value), Box<dyn Error>> {
+    let obj = doc
+        .as_object()
+        .ok_or_else(|| -> Box<dyn Error> { errf!("capsule must be a JSON object") })?;
+    if obj.len() != 1 {
+        return Err(errf!(
+            "capsule must have exactly one key, found {}",
+            obj.len()
+        ));
+    }
+    let (key, body) = obj.iter().next().unwrap();
+    let (family, version) = key
+        .rsplit_once('.')
+        .ok_or_else(|| -> Box<dyn Error> { errf!(r#"malformed capsule key "{key}""#) })?;
+    Ok((family.to_string(), version.to_string(), body.clone()))
+}
+
+/// Migrates a single capsule document. Returns None if it is already at the target version.
+pub fn migrate_document(
+    migrations: &[Migration],
+    doc: &Value,
+    to: &str,
+) -> Result<Option<(String, Value)>, Box<dyn Error>> {
+    let (family, from, mut body) = split_capsule(doc)?;
+    if from == to {
+        return Ok(None);
+    }
+    for step in plan(migrations, &family, &from, to)? {
+        body = (step.apply)(body)?;
+    }
+    let mut out = serde_json::Map::new();
+    out.insert(format!("{family}.{to}"), body);
+    Ok(Some((from, Value::Object(out))))
+}
+
+fn verify(file_name: &OsStr, doc: &Value) -> Result<(), Box<dyn Error>> {
+    let (family, to, _) = split_capsule(doc)?;
+    let expected = format!("{family}.{to}");
+    let version = if file_name == MODULE_FILE_NAME {
+        let capsule: CatalogModuleCapsule = serde_json::from_value(doc.clone())?;
+        capsule.version().to_string()
+    } else {
+        let capsule: CatalogMirrorsCapsule = serde_json::from_value(doc.clone())?;
+        capsule.version().to_string()
+    };
+    if version != expected {
+        return Err(errf!(
+            "migrated capsule has version {version}, expected {expected}"
+        ));
+    }
+    Ok(())
+}
+
+fn collect(
+    dir_path: &Path,
+    to: &str,
+    opts: &WalkOptions,
+) -> Result<Vec<(Migrated, Value)>, Box<dyn Error>> {
+    let mut result = Vec::new();
+    let listing = walk::read_dir(dir_path, opts).map_err(|e| e as Box<dyn Error>)?;
+    for path in listing.files {
+        let file_name = path.file_name().unwrap_or_default().to_os_string();
+        if file_name != MODULE_FILE_NAME && file_name != MIRRORS_FILE_NAME {
+            continue;
+        }
+        let contents = fs::read_to_string(&path)?;
+        let doc: Value = serde_json::from_str(&contents)?;
+        let migrated = migrate_document(MIGRATIONS, &doc, to)
+            .and_then(|m| {
+                if let Some((_, out)) = &m {
+                    verify(&file_name, out)?;
+                }
+                Ok(m)
+            })
+            .map_err(|e| -> Box<dyn Error> { errf!("{}: {e}", path.display()) })?;
+        if let Some((from, out)) = migrated {
+            let m = Migrated {
+                path,
+                from,
+                to: to.to_string(),
+            };
+            result.push((m, out));
+        }
+    }
+    for path in listing.dirs.iter() {
+        result.extend(collect(path, to, opts)?);
+    }
+    Ok(result)
+}
+
+/// Rewrites every `_module.json` and `_mirrors.json` under `dir_path` to capsule version `to`.
+/// All files are migrated in memory before any are written, so a failure leaves the catalog untouched.
+/// When `dry_run` is set no files are written.
+pub fn run(
+    dir_path: &Path,
+    to: &str,
+    dry_run: bool,
+    opts: &WalkOptions,
+) -> Result<Vec<Migrated>, Box<dyn Error>> {
+    let mut result = Vec::new();
+    for (m, out) in collect(dir_path, to, opts)? {
+        if !dry_run {
+            let mut output = serde_json::to_string_pretty(&out)?;
+            output.push('\n');
+            fs::write(&m.path, output)?;
+        }
+        result.push(m);
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn rename_releases(mut body: Value) -> Result<Value, Box<dyn Error>> {
+        let releases = body["releases"].take();
+        body.as_object_mut().unwrap().remove("releases");
+        body["lineage"] = releases;
+        Ok(body)
+    }
+
+    fn identity(body: Value) -> Result<Value, Box<dyn Error>> {
+        Ok(body)
+    }
+
+    const TEST_MIGRATIONS: &[Migration] = &[
+        Migration {
+            family: "catalogmodule",
+            from: "v1",
+            to: "v2",
+            apply: rename_releases,
+        },
+        Migration {
+            family: "catalogmodule",
+            from: "v2",
+            to: "v3",
+            apply: identity,
+        },
+    ];
+
+    #[test]
+    fn test_plan() {
+        let steps = plan(TEST_MIGRATIONS, "catalogmodule", "v1", "v3").unwrap();
+        let names: Vec<_> = steps.iter().map(|m| (m.from, m.to)).collect();
+        assert_eq!(names, vec![("v1", "v2"), ("v2", "v3")]);
+        assert!(plan(TEST_MIGRATIONS, "catalogmodule", "v1", "v1")
+            .unwrap()
+            .is_empty());
+        assert!(plan(TEST_MIGRATIONS, "catalogmirrors", "v1", "v2").is_err());
+        assert!(plan(TEST_MIGRATIONS, "catalogmodule", "v3", "v1").is_err());
+    }
+
+    #[test]
+    fn test_migrate_document() {
+        let doc =
+            json!({"catalogmodule.v1": {"name": "foo", "releases": {"a": "b"}, "metadata": {}}});
+        let (from, out) = migrate_document(TEST_MIGRATIONS, &doc, "v2")
+            .unwrap()
+            .unwrap();
+        assert_eq!(from, "v1");
+        assert_eq!(
+            out,
+            json!({"catalogmodule.v2": {"name": "foo", "lineage": {"a": "b"}, "metadata": {}}})
+        );
+        assert_eq!(migrate_document(TEST_MIGRATIONS, &out, "v2").unwrap(), None);
+    }
+}
@@ -1,81 +1,138 @@
-use indexmap::IndexMap;
-use std::collections::BTreeSet;
 use std::error::Error;
 use std::ffi::OsStr;
-use std::fs;
-use std::io::Read;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+
+use rayon::prelude::*;
 
 use crate::catalog_mirrors::CatalogMirrors;
 use crate::catalog_mirrors::CatalogMirrorsCapsule;
+use crate::schema::{self, Schema};
+use crate::str_error::StrError; //required by errf macro
+use crate::walk::{self, WalkError, WalkOptions};
 
 fn merge_mirrors(
     a: CatalogMirrorsCapsule,
     b: CatalogMirrorsCapsule,
-) -> Result<CatalogMirrorsCapsule, Box<dyn Error>> {
-    let mut result: CatalogMirrors;
-    match a {
-        CatalogMirrorsCapsule::V1(m) => result = m,
-        #[allow(unreachable_patterns)]
-        _ => unimplemented!("unknown CatalogMirrorCapsule version"),
-    }
-    let data: CatalogMirrors;
-    match b {
-        CatalogMirrorsCapsule::V1(m) => data = m,
-        #[allow(unreachable_patterns)]
-        _ => unimplemented!("unknown CatalogMirrorCapsule version"),
-    }
+) -> Result<CatalogMirrorsCapsule, WalkError> {
+    let mut result = match a {
+        CatalogMirrorsCapsule::V1(m) => m,
+        CatalogMirrorsCapsule::Unknown { version, .. } => {
+            return Err(errf!(
+                "cannot merge unknown mirrors capsule version {version}"
+            ))
+        }
+    };
+    let data = match b {
+        CatalogMirrorsCapsule::V1(m) => m,
+        CatalogMirrorsCapsule::Unknown { version, .. } => {
+            return Err(errf!(
+                "cannot merge unknown mirrors capsule version {version}"
+            ))
+        }
+    };
     for (wid, wh_list) in data.by_ware.iter() {
-        let entry = result.by_ware.entry(wid.clone()).or_insert(BTreeSet::new());
+        let entry = result.by_ware.entry(wid.clone()).or_default();
         entry.extend(wh_list.iter().cloned())
     }
+    for (key, value) in data.extra.into_iter() {
+        match result.extra.get(&key) {
+            Some(existing) if *existing != value => eprintln!(
+                "WARNING: conflicting values for unknown field {key}: keeping {existing}, ignoring {value}"
+            ),
+            Some(_) => {}
+            None => {
+                result.extra.insert(key, value);
+            }
+        }
+    }
     for (module, inner) in data.by_module.iter() {
         if inner.is_empty() {
             continue;
         }
-        let outer = result
-            .by_module
-            .entry(module.clone())
-            .or_insert(IndexMap::new());
+        let outer = result.by_module.entry(module.clone()).or_default();
         for (packtype, wh_list) in inner.iter() {
-            let entry = outer.entry(packtype.clone()).or_insert(BTreeSet::new());
+            let entry = outer.entry(packtype.clone()).or_default();
             entry.extend(wh_list.iter().cloned());
         }
     }
     Ok(CatalogMirrorsCapsule::V1(result))
 }
 
-fn read_mirrors_json_file(file_path: &Path) -> Result<CatalogMirrorsCapsule, Box<dyn Error>> {
-    let mut file = fs::File::open(file_path)?;
-    let mut contents = String::new();
-    file.read_to_string(&mut contents)?;
-
-    let capsule: CatalogMirrorsCapsule = serde_json::from_str(&contents)?;
+fn read_mirrors_json_file(
+    file_path: &Path,
+    opts: &WalkOptions,
+) -> Result<CatalogMirrorsCapsule, WalkError> {
+    let capsule: CatalogMirrorsCapsule = opts.cache.load(file_path, opts.schema, |contents| {
+        schema::parse(file_path, contents, opts.schema)
+    })?;
 
     Ok(capsule)
 }
 
-pub fn collect(dir_path: &Path) -> Result<CatalogMirrorsCapsule, Box<dyn Error>> {
-    let mut result: CatalogMirrorsCapsule = CatalogMirrorsCapsule::V1(CatalogMirrors {
-        ..Default::default()
-    });
-    for entry in fs::read_dir(dir_path)? {
-        let entry = entry?;
-        let file_type = entry.file_type()?;
-        let path = entry.path();
+/// Merges every `_mirrors.json` found under `dir_path`.
+/// In lenient mode unreadable files are reported and skipped; in strict mode they are errors.
+/// Sub-directories are walked in parallel on the current rayon thread pool,
+/// but results are merged in directory order so the output does not depend on the number of threads.
+pub fn collect(
+    dir_path: &Path,
+    opts: &WalkOptions,
+) -> Result<CatalogMirrorsCapsule, Box<dyn Error>> {
+    let mut result = CatalogMirrorsCapsule::V1(CatalogMirrors::default());
+    for (_, data) in collect_files(dir_path, opts)? {
+        result = merge_mirrors(result, data).map_err(|e| e as Box<dyn Error>)?;
+    }
+    Ok(result)
+}
 
-        if file_type.is_dir() {
-            let sub_result = collect(&path)?;
-            result = merge_mirrors(result, sub_result)?;
-        } else if file_type.is_file() && path.file_name() == Some(OsStr::new("_mirrors.json")) {
-            let data = read_mirrors_json_file(&path);
-            match data {
-                Ok(n) => {
-                    result = merge_mirrors(result, n)?;
+/// Returns every readable `_mirrors.json` under `dir_path` with its path, in the order
+/// `collect` merges them.
+pub fn collect_files(
+    dir_path: &Path,
+    opts: &WalkOptions,
+) -> Result<Vec<(PathBuf, CatalogMirrorsCapsule)>, Box<dyn Error>> {
+    collect_dir(dir_path, opts).map_err(|e| e as Box<dyn Error>)
+}
+
+fn collect_dir(
+    dir_path: &Path,
+    opts: &WalkOptions,
+) -> Result<Vec<(PathBuf, CatalogMirrorsCapsule)>, WalkError> {
+    let listing = walk::read_dir(dir_path, opts)?;
+    // a directory's own _mirrors.json is merged before those of its sub-directories.
+    let mut entries: Vec<(PathBuf, bool)> = listing
+        .files
+        .into_iter()
+        .filter(|path| path.file_name() == Some(OsStr::new("_mirrors.json")))
+        .map(|path| (path, false))
+        .collect();
+    entries.extend(listing.dirs.into_iter().map(|path| (path, true)));
+    let loaded: Vec<_> = entries
+        .par_iter()
+        .map(|(path, is_dir)| {
+            if *is_dir {
+                collect_dir(path, opts)
+            } else {
+                match read_mirrors_json_file(path, opts) {
+                    Ok(CatalogMirrorsCapsule::Unknown { version, .. }) => {
+                        eprintln!(
+                            r#"WARNING: skipping "{}" with unknown capsule version "{version}""#,
+                            path.display()
+                        );
+                        Ok(Vec::new())
+                    }
+                    Ok(n) => Ok(vec![(path.clone(), n)]),
+                    Err(e) if opts.schema == Schema::Strict => Err(e),
+                    Err(e) => {
+                        eprintln!("Error: {e}");
+                        Ok(Vec::new())
+                    }
                 }
-                Err(e) => eprintln!("Error: {e}"),
             }
-        }
+        })
+        .collect();
+    let mut result = Vec::new();
+    for files in loaded {
+        result.extend(files?);
     }
     Ok(result)
 }
@@ -83,6 +140,8 @@ pub fn collect(dir_path: &Path) -> Result<CatalogMirrorsCapsule, Box<dyn Error>>
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::testutil::TempCatalog;
+    use serde_json::json;
     type StringIndexMap<V> = indexmap::IndexMap<String, V>;
     use std::collections::BTreeSet;
 
@@ -116,6 +175,7 @@ mod tests {
             by_module: string_indexmap! {
                 "foo"=> string_indexmap!{"bar"=> string_set!["y", "b"]}
             },
+            extra: string_indexmap! {"signedBy" => json!("alice"), "region" => json!("eu")},
         });
         let b = CatalogMirrorsCapsule::V1(CatalogMirrors {
             by_ware: string_indexmap! {"foo" => string_set!["c", "e", "d"], "bar"=> string_set!["b"]},
@@ -123,6 +183,8 @@ mod tests {
                 "foo"=>string_indexmap!{"bar"=>string_set!["x", "a"]},
                 "bar"=>string_indexmap!{"grill"=>string_set!["m", "o"]}
             },
+            // a conflicting unknown field keeps the first value, with a warning.
+            extra: string_indexmap! {"signedBy" => json!("bob"), "contact" => json!("ops")},
         });
         let expect = CatalogMirrorsCapsule::V1(CatalogMirrors {
             by_ware: string_indexmap! {"foo" => string_set!["a","c","d", "e"], "bar"=> string_set!["b"]},
@@ -130,8 +192,57 @@ mod tests {
             "foo"=> string_indexmap!{"bar"=> string_set!["a", "b", "x", "y"]},
             "bar"=>string_indexmap!{"grill"=>string_set!["m", "o"]}
             },
+            extra: string_indexmap! {
+                "signedBy" => json!("alice"), "region" => json!("eu"), "contact" => json!("ops")
+            },
         });
         let result = merge_mirrors(a, b).unwrap();
         assert_eq!(expect, result, "expected left and got right");
     }
+
+    #[test]
+    fn test_collect_nested() {
+        let catalog = TempCatalog::new();
+        let add = |dir: &str, maintainer: &str| {
+            catalog.add_mirrors(
+                dir,
+                json!({
+                    "byModule": {dir: {"tar": [format!("ca+https://{dir}.mirror")]}},
+                    "maintainer": maintainer
+                }),
+            );
+        };
+        // "0ad" lists before "_mirrors.json", but a directory's own file still merges first.
+        add("example.org/0ad", "team 2");
+        add("example.org", "team 1");
+        add("example.org/foo/bar", "team 4");
+        add("example.org/foo", "team 3");
+        let opts = Default::default();
+        let files = collect_files(&catalog.path, &opts).unwrap();
+        let paths: Vec<_> = files
+            .iter()
+            .map(|(p, _)| p.strip_prefix(&catalog.path).unwrap().to_path_buf())
+            .collect();
+        assert_eq!(paths[0], Path::new("example.org/_mirrors.json"));
+        let position = |p: &str| paths.iter().position(|q| q == Path::new(p)).unwrap();
+        assert!(
+            position("example.org/foo/_mirrors.json")
+                < position("example.org/foo/bar/_mirrors.json")
+        );
+        let CatalogMirrorsCapsule::V1(merged) = collect(&catalog.path, &opts).unwrap() else {
+            panic!("expected a v1 capsule");
+        };
+        assert!(merged.by_module.contains_key("example.org/foo/bar"));
+        assert_eq!(merged.extra["maintainer"], json!("team 1"));
+    }
+
+    #[test]
+    fn test_merge_unknown() {
+        let a = CatalogMirrorsCapsule::V1(CatalogMirrors::default());
+        let b = CatalogMirrorsCapsule::Unknown {
+            version: "catalogmirrors.v9".into(),
+            data: serde_json::Value::Null,
+        };
+        assert!(merge_mirrors(a, b).is_err());
+    }
 }
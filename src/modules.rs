@@ -0,0 +1,104 @@
+use indexmap::IndexMap;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::path::Path;
+
+use crate::catalog::Catalog;
+
+/// One module as listed by `modules`.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct ModuleSummary {
+    /// The module directory relative to the catalog root.
+    pub path: String,
+    pub releases: usize,
+    pub metadata: IndexMap<String, String>,
+}
+
+/// Every module of `catalog` by name.
+pub fn list(root: &Path, catalog: &Catalog) -> BTreeMap<String, ModuleSummary> {
+    catalog
+        .modules
+        .iter()
+        .map(|m| {
+            let path = m.path.strip_prefix(root).unwrap_or(&m.path);
+            let summary = ModuleSummary {
+                path: path.to_string_lossy().into_owned(),
+                releases: m.releases.len(),
+                metadata: m.module.metadata.clone(),
+            };
+            (m.module.name.clone(), summary)
+        })
+        .collect()
+}
+
+// a namespace segment of module names, which can itself be a module.
+#[derive(Default)]
+struct Node {
+    releases: Option<usize>,
+    children: BTreeMap<String, Node>,
+}
+
+fn write_node(out: &mut String, name: &str, node: &Node, depth: usize) {
+    write!(out, "{:indent$}{name}", "", indent = depth * 2).unwrap();
+    if !node.children.is_empty() {
+        out.push('/');
+    }
+    match node.releases {
+        Some(1) => out.push_str(" (1 release)"),
+        Some(n) => write!(out, " ({n} releases)").unwrap(),
+        None => {}
+    }
+    out.push('\n');
+    for (child_name, child) in node.children.iter() {
+        write_node(out, child_name, child, depth + 1);
+    }
+}
+
+/// Renders module names as a tree of their `/`-separated namespace segments.
+pub fn tree(modules: &BTreeMap<String, ModuleSummary>) -> String {
+    let mut root = Node::default();
+    for (name, summary) in modules.iter() {
+        let mut node = &mut root;
+        for segment in name.split('/') {
+            node = node.children.entry(segment.to_string()).or_default();
+        }
+        node.releases = Some(summary.releases);
+    }
+    let mut out = String::new();
+    for (name, node) in root.children.iter() {
+        write_node(&mut out, name, node, 0);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TempCatalog;
+    use expect_test::expect;
+
+    #[test]
+    fn test_modules() {
+        let catalog = TempCatalog::new();
+        catalog.add_module("warpsys.org/bash", &[("v1", &[]), ("v2", &[])]);
+        catalog.add_module("warpsys.org/diffutils", &[("v1", &[])]);
+        catalog.add_module("warpsys.org/tools", &[]);
+        catalog.add_module("warpsys.org/tools/grep", &[("v1", &[])]);
+        catalog.add_module("example.org/foo", &[("v1", &[])]);
+        let loaded = Catalog::load(&catalog.path, &Default::default()).unwrap();
+        let modules = list(&catalog.path, &loaded);
+        assert_eq!(modules["warpsys.org/bash"].path, "warpsys.org/bash");
+        assert_eq!(modules["warpsys.org/bash"].releases, 2);
+        expect![[r#"
+            example.org/
+              foo (1 release)
+            warpsys.org/
+              bash (2 releases)
+              diffutils (1 release)
+              tools/ (0 releases)
+                grep (1 release)
+        "#]]
+        .assert_eq(&tree(&modules));
+    }
+}
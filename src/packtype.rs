@@ -0,0 +1,237 @@
+use indexmap::IndexMap;
+use std::collections::HashMap;
+use std::error::Error;
+use url::Url;
+
+use crate::wares;
+
+/// What a resolver knows about the mirror it is asked to resolve.
+pub struct ResolveContext<'a> {
+    pub module: &'a str,
+    pub pack_type: &'a str,
+    /// The `caLayouts` of the `--config` file.
+    pub layouts: &'a IndexMap<String, String>,
+}
+
+/// Turns a `byModule` mirror of one packtype into the locations of a ware.
+/// Register implementations in `Resolvers` to support packtypes other than tar and git.
+pub trait PacktypeResolver: Send + Sync {
+    /// Returns the locations of the ware with hash `ware_hash` on `mirror`.
+    /// An empty list means `mirror` cannot serve the ware; the resolver should say why on stderr.
+    fn resolve(
+        &self,
+        mirror: &str,
+        ware_hash: &str,
+        ctx: &ResolveContext,
+    ) -> Result<Vec<String>, Box<dyn Error>>;
+}
+
+/// Resolves content-addressable mirrors such as `ca+https://...`.
+/// Used for tar and for every packtype without a resolver of its own.
+pub struct CaResolver;
+
+impl PacktypeResolver for CaResolver {
+    fn resolve(
+        &self,
+        mirror: &str,
+        ware_hash: &str,
+        ctx: &ResolveContext,
+    ) -> Result<Vec<String>, Box<dyn Error>> {
+        let link = wares::resolve_ca_link(
+            mirror.to_string(),
+            ctx.pack_type,
+            ware_hash.to_string(),
+            ctx.layouts,
+        )?;
+        match link {
+            Some(l) => Ok(vec![l]),
+            None => {
+                eprintln!(
+                    "by module mirrors must have content-addressable scheme (e.g. ca+https): {}:{} = {mirror}",
+                    ctx.module, ctx.pack_type
+                );
+                Ok(Vec::new())
+            }
+        }
+    }
+}
+
+/// Resolves git mirrors, which are repositories rather than content-addressable stores.
+/// The ware hash is the commit, so every mirror yields `<clone url>#<commit>`.
+/// With `forge_archives` set, repositories on known forges also yield a tarball URL of the commit.
+#[derive(Default)]
+pub struct GitResolver {
+    pub forge_archives: bool,
+}
+
+/// Returns the URL of a tarball of `commit` for repositories hosted on a known forge.
+pub fn forge_archive(repo: &str, commit: &str) -> Option<String> {
+    let url = Url::parse(repo).ok()?;
+    let path = url.path().trim_end_matches('/').trim_end_matches(".git");
+    let name = path.rsplit('/').next()?;
+    match url.host_str()? {
+        "github.com" | "codeberg.org" => Some(format!(
+            "https://{}{path}/archive/{commit}.tar.gz",
+            url.host_str()?
+        )),
+        "gitlab.com" => Some(format!(
+            "https://gitlab.com{path}/-/archive/{commit}/{name}-{commit}.tar.gz"
+        )),
+        _ => None,
+    }
+}
+
+impl PacktypeResolver for GitResolver {
+    fn resolve(
+        &self,
+        mirror: &str,
+        ware_hash: &str,
+        _ctx: &ResolveContext,
+    ) -> Result<Vec<String>, Box<dyn Error>> {
+        let mut result = vec![format!("{mirror}#{ware_hash}")];
+        if self.forge_archives {
+            result.extend(forge_archive(mirror, ware_hash));
+        }
+        Ok(result)
+    }
+}
+
+/// The resolver of each packtype. Packtypes without a registered resolver use `CaResolver`.
+///
+/// ```
+/// use catalog_manifest::packtype::{PacktypeResolver, ResolveContext, Resolvers};
+/// use catalog_manifest::wares::ResolveOptions;
+/// use std::error::Error;
+///
+/// struct OciResolver;
+///
+/// impl PacktypeResolver for OciResolver {
+///     fn resolve(
+///         &self,
+///         mirror: &str,
+///         ware_hash: &str,
+///         _ctx: &ResolveContext,
+///     ) -> Result<Vec<String>, Box<dyn Error>> {
+///         Ok(vec![format!("{mirror}@sha256:{ware_hash}")])
+///     }
+/// }
+///
+/// let mut resolvers = Resolvers::default();
+/// resolvers.register("oci", OciResolver);
+/// let resolve = ResolveOptions {
+///     resolvers,
+///     ..Default::default()
+/// };
+/// let mirrors = serde_json::from_str(
+///     r#"{"catalogmirrors.v1": {"byModule": {"example.org/img": {"oci": ["registry.example.org/img"]}}}}"#,
+/// )
+/// .unwrap();
+/// let refs = [("example.org/img:v1:image".to_string(), "oci:abcdef".to_string())].into();
+/// let wares = catalog_manifest::wares::join(mirrors, refs, &resolve).unwrap();
+/// assert_eq!(wares["oci:abcdef"], ["registry.example.org/img@sha256:abcdef"]);
+/// ```
+pub struct Resolvers {
+    by_pack_type: HashMap<String, Box<dyn PacktypeResolver>>,
+    fallback: CaResolver,
+}
+
+impl Default for Resolvers {
+    /// The built-in resolvers for tar and git.
+    fn default() -> Resolvers {
+        let mut resolvers = Resolvers {
+            by_pack_type: HashMap::new(),
+            fallback: CaResolver,
+        };
+        resolvers.register("tar", CaResolver);
+        resolvers.register("git", GitResolver::default());
+        resolvers
+    }
+}
+
+impl Resolvers {
+    /// Registers `resolver` for `pack_type`, replacing any previous one.
+    pub fn register(&mut self, pack_type: &str, resolver: impl PacktypeResolver + 'static) {
+        self.by_pack_type
+            .insert(pack_type.to_string(), Box::new(resolver));
+    }
+
+    pub fn get(&self, pack_type: &str) -> &dyn PacktypeResolver {
+        match self.by_pack_type.get(pack_type) {
+            Some(r) => r.as_ref(),
+            None => &self.fallback,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_forge_archive() {
+        let commit = "e3ec637a29aee4874de2ce2e70e9c9e85761ce22";
+        let cases = vec![
+            (
+                "https://github.com/warptools/warpforge",
+                Some(format!(
+                    "https://github.com/warptools/warpforge/archive/{commit}.tar.gz"
+                )),
+            ),
+            (
+                "https://gitlab.com/group/sub/project.git",
+                Some(format!(
+                    "https://gitlab.com/group/sub/project/-/archive/{commit}/project-{commit}.tar.gz"
+                )),
+            ),
+            ("https://git.example.org/project", None),
+        ];
+        for (repo, expected) in cases {
+            assert_eq!(forge_archive(repo, commit), expected, "{repo}");
+        }
+    }
+
+    struct OciResolver;
+
+    impl PacktypeResolver for OciResolver {
+        fn resolve(
+            &self,
+            mirror: &str,
+            ware_hash: &str,
+            _ctx: &ResolveContext,
+        ) -> Result<Vec<String>, Box<dyn Error>> {
+            Ok(vec![format!("{mirror}@sha256:{ware_hash}")])
+        }
+    }
+
+    #[test]
+    fn test_registry() {
+        let mut resolvers = Resolvers::default();
+        resolvers.register("oci", OciResolver);
+        let layouts = IndexMap::new();
+        let ctx = |pack_type| ResolveContext {
+            module: "example.org/foo",
+            pack_type,
+            layouts: &layouts,
+        };
+        let resolve = |pack_type, mirror| {
+            resolvers
+                .get(pack_type)
+                .resolve(mirror, "abcdefgh", &ctx(pack_type))
+                .unwrap()
+        };
+        assert_eq!(
+            resolve("oci", "docker://registry.example.org/foo"),
+            vec!["docker://registry.example.org/foo@sha256:abcdefgh"]
+        );
+        assert_eq!(
+            resolve("git", "https://example.org/foo.git"),
+            vec!["https://example.org/foo.git#abcdefgh"]
+        );
+        // unregistered packtypes are content-addressed like tar.
+        assert_eq!(
+            resolve("zip", "ca+https://example.org"),
+            vec!["https://example.org/abc/def/abcdefgh"]
+        );
+        assert!(resolve("tar", "https://example.org").is_empty());
+    }
+}
@@ -2,42 +2,82 @@ use std::collections::BTreeMap;
 use std::error::Error;
 use std::ffi::OsStr;
 use std::fs;
-use std::io::Read;
-use std::path::Path;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
-use crate::catalog_releases::{CatalogModule, CatalogModuleCapsule, CatalogRelease};
-use crate::errf;
+use rayon::prelude::*;
+
+use crate::catalog_releases::{CatalogModule, CatalogModuleCapsule, CatalogRelease, ReleaseItem};
+use crate::schema;
 use crate::str_error::StrError; //required by errf macro
+use crate::walk::{self, DirEntries, WalkError, WalkOptions};
 
-const MODULE_FILE_NAME: &str = "_module.json";
-const MODULE_RELEASES_DIR_NAME: &str = "_releases";
+pub const MODULE_FILE_NAME: &str = "_module.json";
+pub const MODULE_RELEASES_DIR_NAME: &str = "_releases";
 
-fn is_module(dir_path: &Path) -> Result<Option<CatalogModule>, Box<dyn Error>> {
+fn is_module(
+    dir_path: &Path,
+    listing: &DirEntries,
+    opts: &WalkOptions,
+) -> Result<Option<CatalogModuleCapsule>, WalkError> {
     let path = dir_path.join(MODULE_FILE_NAME);
-    if let Err(_err) = fs::metadata(&path) {
+    if !listing.files.contains(&path) {
         Ok(None)
     } else {
-        let mut file = fs::File::open(path.as_path())?;
-        let mut contents = String::new();
-        file.read_to_string(&mut contents)?;
-        let capsule: CatalogModuleCapsule = serde_json::from_str(&contents)?;
-        match capsule {
-            CatalogModuleCapsule::V1(m) => Ok(Some(m)),
+        let capsule: CatalogModuleCapsule = opts.cache.load(&path, opts.schema, |contents| {
+            schema::parse(&path, contents, opts.schema)
+        })?;
+        Ok(Some(capsule))
+    }
+}
+
+/// A module found while walking the catalog, with its releases in the order of their file names.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Module {
+    pub path: PathBuf,
+    pub module: CatalogModule,
+    pub releases: Vec<CatalogRelease>,
+}
+
+impl Module {
+    /// Returns every catalog ref of this module with its ware ID.
+    pub fn refs(&self) -> Result<BTreeMap<String, String>, WalkError> {
+        let mut result: BTreeMap<String, String> = BTreeMap::new();
+        for release in self.releases.iter() {
+            for (item, ware_id) in release.items.iter() {
+                let catalog_ref = ReleaseItem {
+                    module: self.module.name.clone(),
+                    version: release.name.clone(),
+                    name: item.clone(),
+                }
+                .to_string();
+                if result.contains_key(&catalog_ref) {
+                    return Err(errf!(
+                        r#"malformed catalog: found duplicate catalog ref item: {catalog_ref}"#
+                    ));
+                }
+                result.insert(catalog_ref, (*ware_id).clone());
+            }
         }
+        Ok(result)
     }
 }
 
 fn process_module(
     module: CatalogModule,
     module_path: &Path,
-) -> Result<BTreeMap<String, String>, Box<dyn Error>> {
-    let mut result: BTreeMap<String, String> = BTreeMap::new();
+    opts: &WalkOptions,
+) -> Result<Module, WalkError> {
+    let mut result = Module {
+        path: module_path.to_path_buf(),
+        module,
+        releases: Vec::new(),
+    };
+    let module = &result.module;
     let releases_path = module_path.join(MODULE_RELEASES_DIR_NAME);
-    if let Err(_) = fs::metadata(&releases_path) {
+    if fs::metadata(&releases_path).is_err() {
         // missing releases directory
         // TODO: This is likely not the best way to implement this check.
-        if module.releases.len() == 0 {
+        if module.releases.is_empty() {
             return Ok(result);
         }
         return Err(errf!(
@@ -45,19 +85,33 @@ fn process_module(
             releases_path.to_string_lossy()
         ));
     }
-    let mut count = 0;
+    let mut paths = Vec::new();
     for entry in fs::read_dir(releases_path)? {
-        count += 1;
         let entry = entry?;
-        let file_type = entry.file_type()?;
         let path = entry.path();
+        let mut file_type = entry.file_type()?;
+        if file_type.is_symlink() && opts.follow_symlinks {
+            file_type = fs::metadata(&path)?.file_type();
+        }
         if !file_type.is_file() {
             return Err(errf!(
                 r#"releases directory contained a non-regular file "{}""#,
                 path.to_string_lossy()
             ));
         }
-        let release = read_release_file(path.as_path())?;
+        paths.push(path);
+    }
+    // directory order differs between filesystems, so output would too.
+    paths.sort();
+    let count = paths.len();
+    // release files are parsed in parallel but processed in file name order.
+    let loaded: Vec<_> = paths
+        .par_iter()
+        .map(|path| read_release_file(path, opts))
+        .collect();
+    let mut releases = Vec::new();
+    for (path, release) in paths.iter().zip(loaded) {
+        let release = release?;
         if !module.releases.contains_key(&release.name) {
             eprintln!(
                 r#"WARNING: release file "{}" contains release "{}" not found in module releases"#,
@@ -65,15 +119,7 @@ fn process_module(
                 release.name,
             );
         }
-        for (item, ware_id) in release.items.iter() {
-            let catalog_ref = format!("{}:{}:{}", module.name, release.name, item);
-            if result.contains_key(&catalog_ref) {
-                return Err(errf!(
-                    r#"malformed catalog: found duplicate catalog ref item: {catalog_ref}"#
-                ));
-            }
-            result.insert(catalog_ref, (*ware_id).clone());
-        }
+        releases.push(release);
     }
     if count != module.releases.len() {
         eprintln!(
@@ -82,19 +128,19 @@ fn process_module(
             module.name,
         )
     }
+    result.releases = releases;
     Ok(result)
 }
 
 fn basename(path: &Path) -> &str {
-    let file_name = path.file_name().unwrap_or(&OsStr::new(""));
+    let file_name = path.file_name().unwrap_or(OsStr::new(""));
     file_name.to_str().unwrap_or("")
 }
 
-fn read_release_file(path: &Path) -> Result<CatalogRelease, Box<dyn Error>> {
-    let mut file = fs::File::open(path)?;
-    let mut contents = String::new();
-    file.read_to_string(&mut contents)?;
-    let result: CatalogRelease = serde_json::from_str(&contents)?;
+fn read_release_file(path: &Path, opts: &WalkOptions) -> Result<CatalogRelease, WalkError> {
+    let result: CatalogRelease = opts.cache.load(path, opts.schema, |contents| {
+        schema::parse(path, contents, opts.schema)
+    })?;
     let file_name = basename(path);
     if !file_name.ends_with(".json") {
         return Err(errf!(
@@ -113,33 +159,117 @@ fn read_release_file(path: &Path) -> Result<CatalogRelease, Box<dyn Error>> {
     Ok(result)
 }
 
-pub fn collect(dir_path: &PathBuf) -> Result<BTreeMap<String, String>, Box<dyn Error>> {
+/// Walks the catalog at `dir_path` and returns every catalog ref with its ware ID.
+/// Sub-directories are walked in parallel on the current rayon thread pool;
+/// the result does not depend on the number of threads.
+pub fn collect(
+    dir_path: &Path,
+    opts: &WalkOptions,
+) -> Result<BTreeMap<String, String>, Box<dyn Error>> {
+    catalog_refs(&collect_modules(dir_path, opts)?)
+}
+
+/// Returns the catalog refs of all `modules`. A ref found in more than one module is an error.
+pub fn catalog_refs(modules: &[Module]) -> Result<BTreeMap<String, String>, Box<dyn Error>> {
     let mut result: BTreeMap<String, String> = BTreeMap::new();
-    let module = is_module(dir_path)?;
-    if let Some(m) = module {
-        let result = process_module(m, dir_path)?;
-        return Ok(result);
+    for module in modules {
+        for (key, value) in module.refs().map_err(|e| e as Box<dyn Error>)? {
+            // copy non-duplicate key/value pairs into result.
+            match result.get_mut(&key) {
+                Some(_) => {
+                    // I could reasonably do something else here
+                    // However, I do not expect to encounter a malformed catalog in the near future.
+                    return Err(errf!(
+                        "malformed catalog: found duplicate catalog ref item: {key}"
+                    ));
+                }
+                None => result.insert(key, value),
+            };
+        }
     }
-    // non-modules recurse into sub-directories
-    for entry in fs::read_dir(dir_path)? {
-        let entry = entry?;
-        let file_type = entry.file_type()?;
-        if file_type.is_dir() {
-            let sub_result = collect(&entry.path())?;
-            for (key, value) in sub_result {
-                // copy non-duplicate key/value pairs into result.
-                match result.get_mut(&key) {
-                    Some(_) => {
-                        // I could reasonably do something else here
-                        // However, I do not expect to encounter a malformed catalog in the near future.
-                        return Err(errf!(
-                            "malformed catalog: found duplicate catalog ref item: {key}"
-                        ));
-                    }
-                    None => result.insert(key, value),
-                };
-            }
+    Ok(result)
+}
+
+/// Walks the catalog at `dir_path` and returns every module in directory order.
+pub fn collect_modules(dir_path: &Path, opts: &WalkOptions) -> Result<Vec<Module>, Box<dyn Error>> {
+    collect_dir(dir_path, opts).map_err(|e| e as Box<dyn Error>)
+}
+
+fn collect_dir(dir_path: &Path, opts: &WalkOptions) -> Result<Vec<Module>, WalkError> {
+    let mut result = Vec::new();
+    let listing = walk::read_dir(dir_path, opts)?;
+    match is_module(dir_path, &listing, opts)? {
+        Some(CatalogModuleCapsule::V1(m)) => {
+            result.push(process_module(m, dir_path, opts)?);
         }
+        Some(CatalogModuleCapsule::Unknown { version, .. }) => {
+            eprintln!(
+                r#"WARNING: skipping module "{}" with unknown capsule version "{version}""#,
+                dir_path.display()
+            );
+        }
+        None => {}
+    }
+    // modules may contain nested modules, so every directory is walked.
+    let sub_results: Vec<_> = listing
+        .dirs
+        .par_iter()
+        .map(|path| collect_dir(path, opts))
+        .collect();
+    for sub_result in sub_results {
+        result.extend(sub_result?);
     }
     Ok(result)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TempCatalog;
+
+    #[test]
+    fn test_collect_nested() {
+        let catalog = TempCatalog::new();
+        catalog.add_module("example.org/foo", &[("v1", &[("src", "tar:abcdefgh")])]);
+        catalog.add_module("example.org/foo/bar", &[("v2", &[("src", "tar:ijklmnop")])]);
+        let opts = Default::default();
+        let modules = collect_modules(&catalog.path, &opts).unwrap();
+        let mut names: Vec<_> = modules
+            .iter()
+            .map(|m| {
+                (
+                    m.module.name.as_str(),
+                    m.path.strip_prefix(&catalog.path).unwrap(),
+                )
+            })
+            .collect();
+        names.sort();
+        assert_eq!(
+            names,
+            vec![
+                ("example.org/foo", Path::new("example.org/foo")),
+                ("example.org/foo/bar", Path::new("example.org/foo/bar")),
+            ]
+        );
+        let refs = collect(&catalog.path, &opts).unwrap();
+        assert_eq!(refs["example.org/foo:v1:src"], "tar:abcdefgh");
+        assert_eq!(refs["example.org/foo/bar:v2:src"], "tar:ijklmnop");
+    }
+
+    #[test]
+    fn test_release_order() {
+        let catalog = TempCatalog::new();
+        let releases: Vec<(&str, &[(&str, &str)])> = ["v2", "v10", "v1", "v1.1"]
+            .into_iter()
+            .map(|r| (r, &[] as &[(&str, &str)]))
+            .collect();
+        catalog.add_module("example.org/foo", &releases);
+        let modules = collect_modules(&catalog.path, &Default::default()).unwrap();
+        let names: Vec<_> = modules[0]
+            .releases
+            .iter()
+            .map(|r| r.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["v1.1", "v1", "v10", "v2"]);
+    }
+}
@@ -0,0 +1,172 @@
+use regex::Regex;
+use serde::Deserialize;
+use std::error::Error;
+use std::fmt;
+
+use crate::str_error::StrError; //required by errf macro
+use crate::wares::ResolvedWares;
+
+/// A rewrite rule as written in the config file: exactly one of `prefix` and `regex`,
+/// and the `replacement`. Regex replacements can refer to capture groups as `$1` or `${name}`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RuleConfig {
+    pub prefix: Option<String>,
+    pub regex: Option<String>,
+    pub replacement: String,
+}
+
+#[derive(Debug)]
+enum Pattern {
+    Prefix(String),
+    Regex(Regex),
+}
+
+/// A compiled rewrite rule.
+#[derive(Debug)]
+pub struct Rule {
+    pattern: Pattern,
+    replacement: String,
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.pattern {
+            Pattern::Prefix(p) => write!(f, "{p}={}", self.replacement),
+            Pattern::Regex(r) => write!(f, "re:{}={}", r.as_str(), self.replacement),
+        }
+    }
+}
+
+impl Rule {
+    pub fn prefix(prefix: &str, replacement: &str) -> Rule {
+        Rule {
+            pattern: Pattern::Prefix(prefix.to_string()),
+            replacement: replacement.to_string(),
+        }
+    }
+
+    pub fn regex(pattern: &str, replacement: &str) -> Result<Rule, Box<dyn Error>> {
+        let regex = Regex::new(pattern).map_err(|e| errf!("invalid rewrite regex: {e}"))?;
+        Ok(Rule {
+            pattern: Pattern::Regex(regex),
+            replacement: replacement.to_string(),
+        })
+    }
+
+    /// Parses a rule given on the command line as `FROM=TO`, where `FROM` is a URL prefix
+    /// or, starting with `re:`, a regular expression.
+    pub fn parse(s: &str) -> Result<Rule, Box<dyn Error>> {
+        let (from, to) = s
+            .split_once('=')
+            .ok_or_else(|| errf!(r#"rewrite rule "{s}" must have the form FROM=TO"#))?;
+        match from.strip_prefix("re:") {
+            Some(pattern) => Rule::regex(pattern, to),
+            None => Ok(Rule::prefix(from, to)),
+        }
+    }
+
+    pub fn from_config(config: &RuleConfig) -> Result<Rule, Box<dyn Error>> {
+        match (&config.prefix, &config.regex) {
+            (Some(prefix), None) => Ok(Rule::prefix(prefix, &config.replacement)),
+            (None, Some(regex)) => Rule::regex(regex, &config.replacement),
+            _ => Err(errf!(
+                "a rewrite rule must have exactly one of \"prefix\" and \"regex\""
+            )),
+        }
+    }
+
+    // returns the rewritten url, or None if the rule does not match.
+    fn apply(&self, url: &str) -> Option<String> {
+        match &self.pattern {
+            Pattern::Prefix(p) => url
+                .strip_prefix(p.as_str())
+                .map(|rest| format!("{}{rest}", self.replacement)),
+            Pattern::Regex(r) if r.is_match(url) => {
+                Some(r.replace(url, self.replacement.as_str()).into_owned())
+            }
+            Pattern::Regex(_) => None,
+        }
+    }
+}
+
+/// An ordered list of rewrite rules. Only the first rule matching a URL is applied.
+#[derive(Debug, Default)]
+pub struct Rewrites {
+    pub rules: Vec<Rule>,
+    /// Print every rewritten URL and the rule which rewrote it to stderr.
+    pub trace: bool,
+}
+
+impl Rewrites {
+    pub fn rewrite(&self, url: &str) -> String {
+        for (i, rule) in self.rules.iter().enumerate() {
+            if let Some(rewritten) = rule.apply(url) {
+                if self.trace {
+                    eprintln!("rewrite: {url} -> {rewritten} (rule {}: {rule})", i + 1);
+                }
+                return rewritten;
+            }
+        }
+        url.to_string()
+    }
+
+    /// Rewrites every URL of resolved wares, keeping their order.
+    /// URLs which become duplicates of an earlier URL are dropped.
+    pub fn apply(&self, wares: ResolvedWares) -> ResolvedWares {
+        if self.rules.is_empty() {
+            return wares;
+        }
+        wares
+            .into_iter()
+            .map(|(ware_id, urls)| {
+                let mut rewritten: Vec<String> = Vec::new();
+                for url in urls.iter().map(|u| self.rewrite(u)) {
+                    if !rewritten.contains(&url) {
+                        rewritten.push(url);
+                    }
+                }
+                (ware_id, rewritten)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rewrite() {
+        let rewrites = Rewrites {
+            rules: vec![
+                Rule::parse("https://ftp.gnu.org/=https://proxy.internal/gnu/").unwrap(),
+                Rule::parse(r"re:^https://([a-z-]+)\.s3\.[^/]+/=https://proxy.internal/s3/$1/")
+                    .unwrap(),
+                Rule::parse("https://=https://proxy.internal/other/").unwrap(),
+            ],
+            trace: false,
+        };
+        let cases = vec![
+            // the last rule matches as well, but earlier rules win.
+            (
+                "https://ftp.gnu.org/gnu/bash/bash-5.1.16.tar.gz",
+                "https://proxy.internal/gnu/gnu/bash/bash-5.1.16.tar.gz",
+            ),
+            (
+                "https://warpsys-wares.s3.fr-par.scw.cloud/5K7/rek/5K7rek",
+                "https://proxy.internal/s3/warpsys-wares/5K7/rek/5K7rek",
+            ),
+            (
+                "https://example.org/x",
+                "https://proxy.internal/other/example.org/x",
+            ),
+            ("http://example.org/x", "http://example.org/x"),
+        ];
+        for (url, expected) in cases {
+            assert_eq!(rewrites.rewrite(url), expected, "{url}");
+        }
+        assert!(Rule::parse("no-separator").is_err());
+        assert!(Rule::parse("re:(=x").is_err());
+    }
+}
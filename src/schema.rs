@@ -0,0 +1,161 @@
+use clap::ValueEnum;
+use serde::de::DeserializeOwned;
+use serde_path_to_error::Segment;
+use std::path::Path;
+
+use crate::catalog_errors::MalformedCatalog;
+use crate::catalog_mirrors::CatalogMirrorsCapsule;
+use crate::catalog_releases::{CatalogModuleCapsule, CatalogRelease};
+use crate::walk::WalkError;
+
+/// How strictly catalog documents are checked while they are read.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Schema {
+    /// Unknown fields and capsule versions are kept and skipped with a warning.
+    #[default]
+    Lenient,
+    /// Unknown fields and capsule versions are errors.
+    Strict,
+}
+
+/// Implemented by catalog documents to list the fields this crate does not understand.
+pub trait UnknownFields {
+    /// Returns a JSON pointer for every unknown field in the document.
+    fn unknown_fields(&self) -> Vec<String>;
+}
+
+fn escape(token: &str) -> String {
+    token.replace('~', "~0").replace('/', "~1")
+}
+
+/// Builds a JSON pointer from its unescaped reference tokens.
+pub fn json_pointer<S: AsRef<str>>(tokens: &[S]) -> String {
+    tokens
+        .iter()
+        .map(|t| format!("/{}", escape(t.as_ref())))
+        .collect()
+}
+
+fn extra_pointers<V>(prefix: &[&str], extra: &indexmap::IndexMap<String, V>) -> Vec<String> {
+    extra
+        .keys()
+        .map(|k| {
+            let mut tokens = prefix.to_vec();
+            tokens.push(k);
+            json_pointer(&tokens)
+        })
+        .collect()
+}
+
+impl UnknownFields for CatalogModuleCapsule {
+    fn unknown_fields(&self) -> Vec<String> {
+        match self {
+            CatalogModuleCapsule::V1(m) => extra_pointers(&[self.version()], &m.extra),
+            CatalogModuleCapsule::Unknown { version, .. } => vec![json_pointer(&[version])],
+        }
+    }
+}
+
+impl UnknownFields for CatalogMirrorsCapsule {
+    fn unknown_fields(&self) -> Vec<String> {
+        match self {
+            CatalogMirrorsCapsule::V1(m) => extra_pointers(&[self.version()], &m.extra),
+            CatalogMirrorsCapsule::Unknown { version, .. } => vec![json_pointer(&[version])],
+        }
+    }
+}
+
+impl UnknownFields for CatalogRelease {
+    fn unknown_fields(&self) -> Vec<String> {
+        extra_pointers(&[], &self.extra)
+    }
+}
+
+/// Deserializes a catalog document read from `path`.
+/// Errors name the file and the JSON pointer of the value which failed to parse.
+/// In strict mode any unknown field is also an error.
+pub fn parse<T: DeserializeOwned + UnknownFields>(
+    path: &Path,
+    contents: &str,
+    schema: Schema,
+) -> Result<T, WalkError> {
+    let de = &mut serde_json::Deserializer::from_str(contents);
+    let result: T = serde_path_to_error::deserialize(de).map_err(|e| {
+        let tokens: Vec<String> = e
+            .path()
+            .iter()
+            .filter_map(|s| match s {
+                Segment::Seq { index } => Some(index.to_string()),
+                Segment::Map { key } => Some(key.clone()),
+                Segment::Enum { variant } => Some(variant.clone()),
+                Segment::Unknown => None,
+            })
+            .collect();
+        Box::new(MalformedCatalog {
+            path: path.to_path_buf(),
+            pointer: json_pointer(&tokens),
+            message: e.into_inner().to_string(),
+        })
+    })?;
+    check(path, &result, schema)?;
+    Ok(result)
+}
+
+/// Applies the schema rules to an already deserialized document.
+pub fn check<T: UnknownFields>(path: &Path, doc: &T, schema: Schema) -> Result<(), WalkError> {
+    if schema == Schema::Strict {
+        if let Some(pointer) = doc.unknown_fields().into_iter().next() {
+            return Err(Box::new(MalformedCatalog {
+                path: path.to_path_buf(),
+                pointer,
+                message: String::from("unknown field"),
+            }));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_err<T: DeserializeOwned + UnknownFields + std::fmt::Debug>(
+        contents: &str,
+        schema: Schema,
+    ) -> String {
+        parse::<T>(Path::new("x.json"), contents, schema)
+            .unwrap_err()
+            .to_string()
+    }
+
+    #[test]
+    fn test_json_pointer() {
+        assert_eq!(json_pointer(&["a/b", "c~d", "0"]), "/a~1b/c~0d/0");
+        assert_eq!(json_pointer::<&str>(&[]), "");
+    }
+
+    #[test]
+    fn test_type_mismatch() {
+        let contents =
+            r#"{"catalogmodule.v1": {"name": "a", "releases": {}, "metadata": {"k": 1}}}"#;
+        let err = parse_err::<CatalogModuleCapsule>(contents, Schema::Lenient);
+        assert!(
+            err.starts_with("x.json: /catalogmodule.v1/metadata/k: invalid type: integer `1`"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn test_unknown_fields() {
+        let contents = r#"{"releaseName": "v1", "items": {}, "metadata": {}, "a/b": true}"#;
+        let release: CatalogRelease =
+            parse(Path::new("x.json"), contents, Schema::Lenient).unwrap();
+        assert_eq!(release.unknown_fields(), vec!["/a~1b"]);
+        let err = parse_err::<CatalogRelease>(contents, Schema::Strict);
+        assert_eq!(err, "x.json: /a~1b: unknown field");
+
+        let contents = r#"{"catalogmirrors.v2": {}}"#;
+        let err = parse_err::<CatalogMirrorsCapsule>(contents, Schema::Strict);
+        assert_eq!(err, "x.json: /catalogmirrors.v2: unknown field");
+    }
+}
@@ -0,0 +1,192 @@
+use clap::ValueEnum;
+use indexmap::IndexMap;
+use regex::Regex;
+use serde::Serialize;
+use std::error::Error;
+
+use crate::catalog::Catalog;
+use crate::str_error::StrError; //required by errf macro
+
+/// How the pattern of `search` is matched against a value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Mode {
+    /// The value contains the pattern
+    Substring,
+    /// The whole value matches the pattern, where `*` is any text and `?` any character
+    Glob,
+    /// The value contains a match of the regular expression
+    Regex,
+}
+
+/// A compiled search pattern. Globs are compiled to anchored regular expressions.
+pub enum Pattern {
+    Substring(String),
+    Regex(Regex),
+}
+
+impl Pattern {
+    pub fn new(mode: Mode, pattern: &str) -> Result<Pattern, Box<dyn Error>> {
+        let regex = match mode {
+            Mode::Substring => return Ok(Pattern::Substring(pattern.to_string())),
+            Mode::Glob => glob_regex(pattern),
+            Mode::Regex => pattern.to_string(),
+        };
+        let regex = Regex::new(&regex).map_err(|e| errf!("invalid pattern: {e}"))?;
+        Ok(Pattern::Regex(regex))
+    }
+
+    pub fn is_match(&self, value: &str) -> bool {
+        match self {
+            Pattern::Substring(s) => value.contains(s.as_str()),
+            Pattern::Regex(r) => r.is_match(value),
+        }
+    }
+}
+
+fn glob_regex(glob: &str) -> String {
+    let mut result = String::from("^");
+    for c in glob.chars() {
+        match c {
+            '*' => result.push_str(".*"),
+            '?' => result.push('.'),
+            c => result.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    result.push('$');
+    result
+}
+
+/// Which part of the catalog a pattern matched.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Field {
+    Module,
+    Release,
+    Item,
+    Ware,
+    MetadataKey,
+    MetadataValue,
+}
+
+/// One match of `search`. `catalog_ref` is "module", "module:release" or
+/// "module:release:item", depending on where the match is.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Match {
+    #[serde(rename = "ref")]
+    pub catalog_ref: String,
+    pub field: Field,
+    /// The metadata key of a metadata value.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+    pub value: String,
+}
+
+/// Returns every module name, release name, item name, ware ID and metadata key or value
+/// of `catalog` which matches `pattern`, in catalog order.
+pub fn search(catalog: &Catalog, pattern: &Pattern) -> Vec<Match> {
+    let mut result = Vec::new();
+    for m in catalog.modules.iter() {
+        let name = &m.module.name;
+        check(&mut result, pattern, name, Field::Module, name);
+        check_metadata(&mut result, pattern, name, &m.module.metadata);
+        for release in m.releases.iter() {
+            let release_ref = format!("{name}:{}", release.name);
+            check(
+                &mut result,
+                pattern,
+                &release_ref,
+                Field::Release,
+                &release.name,
+            );
+            check_metadata(&mut result, pattern, &release_ref, &release.metadata);
+            for (item, ware_id) in release.items.iter() {
+                let item_ref = format!("{release_ref}:{item}");
+                check(&mut result, pattern, &item_ref, Field::Item, item);
+                check(&mut result, pattern, &item_ref, Field::Ware, ware_id);
+            }
+        }
+    }
+    result
+}
+
+fn check(result: &mut Vec<Match>, pattern: &Pattern, catalog_ref: &str, field: Field, value: &str) {
+    if pattern.is_match(value) {
+        result.push(Match {
+            catalog_ref: catalog_ref.to_string(),
+            field,
+            key: None,
+            value: value.to_string(),
+        });
+    }
+}
+
+fn check_metadata(
+    result: &mut Vec<Match>,
+    pattern: &Pattern,
+    catalog_ref: &str,
+    metadata: &IndexMap<String, String>,
+) {
+    for (key, value) in metadata.iter() {
+        check(result, pattern, catalog_ref, Field::MetadataKey, key);
+        if pattern.is_match(value) {
+            result.push(Match {
+                catalog_ref: catalog_ref.to_string(),
+                field: Field::MetadataValue,
+                key: Some(key.clone()),
+                value: value.clone(),
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TempCatalog;
+    use expect_test::expect;
+
+    #[test]
+    fn test_search() {
+        let catalog = TempCatalog::new();
+        catalog.add_module(
+            "example.org/foo",
+            &[(
+                "v1.0",
+                &[("src", "tar:abcdefgh"), ("arm64", "tar:12345678")],
+            )],
+        );
+        catalog.add_module(
+            "example.org/arm64-tools",
+            &[("v2", &[("amd64", "tar:ijklmnop")])],
+        );
+        catalog.write(
+            "example.org/arm64-tools/_module.json",
+            r#"{"catalogmodule.v1": {"name": "example.org/arm64-tools",
+                "releases": {"v2": "zM5Kexample"},
+                "metadata": {"upstream": "https://example.org/arm64"}}}"#,
+        );
+        let loaded = Catalog::load(&catalog.path, &Default::default()).unwrap();
+        let render = |mode, pattern| {
+            let pattern = Pattern::new(mode, pattern).unwrap();
+            search(&loaded, &pattern)
+                .iter()
+                .map(|m| serde_json::to_string(m).unwrap() + "\n")
+                .collect::<String>()
+        };
+        expect![[r#"
+            {"ref":"example.org/arm64-tools","field":"module","value":"example.org/arm64-tools"}
+            {"ref":"example.org/arm64-tools","field":"metadataValue","key":"upstream","value":"https://example.org/arm64"}
+            {"ref":"example.org/foo:v1.0:arm64","field":"item","value":"arm64"}
+        "#]]
+        .assert_eq(&render(Mode::Substring, "arm64"));
+        expect![[r#"
+            {"ref":"example.org/foo:v1.0","field":"release","value":"v1.0"}
+        "#]]
+        .assert_eq(&render(Mode::Glob, "v?.0"));
+        expect![[r#"
+            {"ref":"example.org/foo:v1.0:arm64","field":"ware","value":"tar:12345678"}
+        "#]]
+        .assert_eq(&render(Mode::Regex, "^tar:[0-9]+$"));
+        assert!(Pattern::new(Mode::Regex, "(").is_err());
+    }
+}
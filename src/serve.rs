@@ -0,0 +1,211 @@
+use percent_encoding::percent_decode_str;
+use serde_json::{json, Value};
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::thread;
+use tiny_http::{Header, Method, Response, Server};
+
+use crate::catalog::Catalog;
+use crate::releases::Module;
+use crate::str_error::StrError; //required by errf macro
+use crate::wares::{ResolveOptions, ResolvedWares};
+use crate::watch;
+
+const WORKERS: usize = 4;
+
+/// The in-memory model behind the API, replaced as a whole when the catalog is reloaded.
+pub struct State {
+    pub root: PathBuf,
+    pub catalog: Catalog,
+    pub wares: ResolvedWares,
+}
+
+impl State {
+    pub fn new(
+        root: &Path,
+        catalog: Catalog,
+        resolve: &ResolveOptions,
+    ) -> Result<State, Box<dyn Error>> {
+        let wares = catalog.wares(resolve)?;
+        Ok(State {
+            root: root.to_path_buf(),
+            catalog,
+            wares,
+        })
+    }
+
+    fn relative(&self, path: &Path) -> String {
+        path.strip_prefix(&self.root)
+            .unwrap_or(path)
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    fn module_summary(&self, m: &Module) -> Value {
+        let releases: Vec<&String> = m.releases.iter().map(|r| &r.name).collect();
+        json!({
+            "path": self.relative(&m.path),
+            "releases": releases,
+            "metadata": m.module.metadata,
+        })
+    }
+}
+
+fn not_found(what: &str) -> (u16, Value) {
+    (404, json!({ "error": format!("{what} not found") }))
+}
+
+/// Answers a GET request for `path` from `state`. Returns the HTTP status and a JSON body.
+pub fn route(state: &State, path: &str) -> (u16, Value) {
+    let path = path.split('?').next().unwrap_or("");
+    let path = percent_decode_str(path).decode_utf8_lossy();
+    let segments: Vec<&str> = path.trim_matches('/').splitn(2, '/').collect();
+    match segments.as_slice() {
+        ["modules"] => {
+            let modules: serde_json::Map<String, Value> = state
+                .catalog
+                .modules
+                .iter()
+                .map(|m| (m.module.name.clone(), state.module_summary(m)))
+                .collect();
+            (200, Value::Object(modules))
+        }
+        ["modules", name] => match state.catalog.module(name) {
+            Some(m) => {
+                let mut body = state.module_summary(m);
+                body["name"] = json!(m.module.name);
+                body["releases"] = json!(m.releases);
+                (200, body)
+            }
+            None => not_found("module"),
+        },
+        ["refs", catalog_ref] => match state.catalog.refs.get(*catalog_ref) {
+            Some(ware_id) => (200, json!({ "ref": catalog_ref, "wareId": ware_id })),
+            None => not_found("catalog ref"),
+        },
+        ["wares", ware_id] => match state.wares.get(*ware_id) {
+            Some(urls) => {
+                let refs: Vec<&String> = state
+                    .catalog
+                    .refs
+                    .iter()
+                    .filter(|(_, w)| w == ware_id)
+                    .map(|(r, _)| r)
+                    .collect();
+                (
+                    200,
+                    json!({ "wareId": ware_id, "refs": refs, "urls": urls }),
+                )
+            }
+            None => not_found("ware"),
+        },
+        ["mirrors"] => (200, json!(state.catalog.mirrors)),
+        _ => not_found("endpoint"),
+    }
+}
+
+/// Serves the read-only API on `listen` until the process is stopped.
+/// `load` is called once at startup and again whenever the catalog at `root` changes;
+/// if a reload fails the previous state keeps being served.
+pub fn run(
+    listen: &str,
+    root: &Path,
+    load: impl Fn() -> Result<State, Box<dyn Error>> + Send + 'static,
+) -> Result<(), Box<dyn Error>> {
+    let state = Arc::new(RwLock::new(load()?));
+    let server = Arc::new(Server::http(listen).map_err(|e| errf!("{listen}: {e}"))?);
+    eprintln!(
+        "serving {} on http://{}",
+        root.display(),
+        server.server_addr()
+    );
+
+    let reload_state = Arc::clone(&state);
+    let reload_root = root.to_path_buf();
+    thread::spawn(move || {
+        let result = watch::wait_for_changes(&reload_root, || match load() {
+            Ok(s) => *reload_state.write().unwrap() = s,
+            Err(e) => eprintln!("Error: reload failed, serving previous catalog: {e}"),
+        });
+        if let Err(e) = result {
+            eprintln!("Error: no longer watching the catalog: {e}");
+        }
+    });
+
+    let json_header = Header::from_bytes("Content-Type", "application/json").unwrap();
+    let workers: Vec<_> = (0..WORKERS)
+        .map(|_| {
+            let server = Arc::clone(&server);
+            let state = Arc::clone(&state);
+            let json_header = json_header.clone();
+            thread::spawn(move || {
+                for request in server.incoming_requests() {
+                    let (status, body) = if *request.method() == Method::Get {
+                        route(&state.read().unwrap(), request.url())
+                    } else {
+                        (405, json!({ "error": "method not allowed" }))
+                    };
+                    let response = Response::from_string(body.to_string())
+                        .with_status_code(status)
+                        .with_header(json_header.clone());
+                    if let Err(e) = request.respond(response) {
+                        eprintln!("Error: {e}");
+                    }
+                }
+            })
+        })
+        .collect();
+    for worker in workers {
+        let _ = worker.join();
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TempCatalog;
+
+    fn state(catalog: &TempCatalog) -> State {
+        catalog.add_module("example.org/foo", &[("v1.0", &[("src", "tar:abcdefghij")])]);
+        catalog.add_mirrors(
+            "example.org",
+            json!({"byModule": {"example.org/foo": {"tar": ["ca+https://mirror.example.org"]}}}),
+        );
+        let loaded = Catalog::load(&catalog.path, &Default::default()).unwrap();
+        State::new(&catalog.path, loaded, &Default::default()).unwrap()
+    }
+
+    #[test]
+    fn test_route() {
+        let catalog = TempCatalog::new();
+        let state = state(&catalog);
+
+        let (status, body) = route(&state, "/modules");
+        assert_eq!(status, 200);
+        assert_eq!(body["example.org/foo"]["path"], "example.org/foo");
+        assert_eq!(body["example.org/foo"]["releases"], json!(["v1.0"]));
+
+        let (status, body) = route(&state, "/modules/example.org/foo");
+        assert_eq!(status, 200);
+        assert_eq!(body["releases"][0]["items"]["src"], "tar:abcdefghij");
+
+        let (status, body) = route(&state, "/refs/example.org%2Ffoo:v1.0:src");
+        assert_eq!(status, 200);
+        assert_eq!(body["wareId"], "tar:abcdefghij");
+
+        let (status, body) = route(&state, "/wares/tar:abcdefghij");
+        assert_eq!(status, 200);
+        assert_eq!(
+            body["urls"],
+            json!(["https://mirror.example.org/abc/def/abcdefghij"])
+        );
+        assert_eq!(body["refs"], json!(["example.org/foo:v1.0:src"]));
+
+        let (status, _) = route(&state, "/mirrors");
+        assert_eq!(status, 200);
+        assert_eq!(route(&state, "/modules/example.org/bar").0, 404);
+        assert_eq!(route(&state, "/nothing").0, 404);
+    }
+}
@@ -0,0 +1,190 @@
+use indexmap::IndexMap;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use crate::catalog_mirrors::CatalogMirrorsCapsule;
+use crate::releases::Module;
+use crate::wares::ResolvedWares;
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct ItemDetail {
+    pub ware: String,
+    /// Download locations of the ware, as listed by `wares`.
+    pub urls: Vec<String>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct ReleaseDetail {
+    pub name: String,
+    pub metadata: IndexMap<String, String>,
+    pub items: IndexMap<String, ItemDetail>,
+}
+
+/// Everything known about one module, as printed by `show`.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct ModuleDetail {
+    pub name: String,
+    /// The module directory relative to the catalog root.
+    pub path: String,
+    pub metadata: IndexMap<String, String>,
+    /// Releases in the order of `_module.json`.
+    pub releases: Vec<ReleaseDetail>,
+    /// byModule mirrors by packtype, each with the `_mirrors.json` files declaring it.
+    pub mirrors: BTreeMap<String, BTreeMap<String, Vec<String>>>,
+}
+
+/// Collects the detail of `module`. `mirror_files` are the `_mirrors.json` files of the
+/// catalog at `root` as returned by `mirrors::collect_files`, and `resolved` its resolved wares.
+pub fn show(
+    root: &Path,
+    module: &Module,
+    mirror_files: &[(PathBuf, CatalogMirrorsCapsule)],
+    resolved: &ResolvedWares,
+) -> ModuleDetail {
+    let relative = |path: &Path| {
+        path.strip_prefix(root)
+            .unwrap_or(path)
+            .to_string_lossy()
+            .into_owned()
+    };
+    let mut releases: Vec<_> = module.releases.iter().collect();
+    // releases missing from `_module.json` go last, in the order they were read.
+    releases.sort_by_key(|r| {
+        module
+            .module
+            .releases
+            .get_index_of(&r.name)
+            .unwrap_or(usize::MAX)
+    });
+    let releases = releases
+        .into_iter()
+        .map(|r| ReleaseDetail {
+            name: r.name.clone(),
+            metadata: r.metadata.clone(),
+            items: r
+                .items
+                .iter()
+                .map(|(item, ware_id)| {
+                    let detail = ItemDetail {
+                        ware: ware_id.clone(),
+                        urls: resolved.get(ware_id).cloned().unwrap_or_default(),
+                    };
+                    (item.clone(), detail)
+                })
+                .collect(),
+        })
+        .collect();
+    let mut mirrors: BTreeMap<String, BTreeMap<String, Vec<String>>> = BTreeMap::new();
+    for (path, capsule) in mirror_files {
+        let CatalogMirrorsCapsule::V1(data) = capsule else {
+            continue;
+        };
+        for (pack_type, urls) in data
+            .by_module
+            .get(&module.module.name)
+            .into_iter()
+            .flatten()
+        {
+            let by_url = mirrors.entry(pack_type.clone()).or_default();
+            for url in urls.iter() {
+                by_url.entry(url.clone()).or_default().push(relative(path));
+            }
+        }
+    }
+    for sources in mirrors.values_mut().flat_map(|by_url| by_url.values_mut()) {
+        sources.sort();
+    }
+    ModuleDetail {
+        name: module.module.name.clone(),
+        path: relative(&module.path),
+        metadata: module.module.metadata.clone(),
+        releases,
+        mirrors,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::catalog::Catalog;
+    use crate::mirrors;
+    use crate::testutil::TempCatalog;
+    use expect_test::expect;
+    use serde_json::json;
+
+    #[test]
+    fn test_show() {
+        let catalog = TempCatalog::new();
+        catalog.add_module(
+            "example.org/foo",
+            &[
+                ("v2", &[("src", "tar:abcdefgh")]),
+                ("v1", &[("src", "tar:12345678")]),
+            ],
+        );
+        catalog.add_mirrors(
+            "example.org/foo",
+            json!({"byModule": {"example.org/foo": {"tar": ["ca+https://a.example.org"]}}}),
+        );
+        catalog.add_mirrors(
+            "example.org",
+            json!({"byModule": {"example.org/foo": {"tar": [
+                "ca+https://a.example.org",
+                "ca+https://b.example.org",
+            ]}}}),
+        );
+        let opts = Default::default();
+        let loaded = Catalog::load(&catalog.path, &opts).unwrap();
+        let files = mirrors::collect_files(&catalog.path, &opts).unwrap();
+        let resolved = loaded.wares(&Default::default()).unwrap();
+        let module = loaded.module("example.org/foo").unwrap();
+        let detail = show(&catalog.path, module, &files, &resolved);
+        expect![[r#"
+            {
+              "name": "example.org/foo",
+              "path": "example.org/foo",
+              "metadata": {},
+              "releases": [
+                {
+                  "name": "v2",
+                  "metadata": {},
+                  "items": {
+                    "src": {
+                      "ware": "tar:abcdefgh",
+                      "urls": [
+                        "https://a.example.org/abc/def/abcdefgh",
+                        "https://b.example.org/abc/def/abcdefgh"
+                      ]
+                    }
+                  }
+                },
+                {
+                  "name": "v1",
+                  "metadata": {},
+                  "items": {
+                    "src": {
+                      "ware": "tar:12345678",
+                      "urls": [
+                        "https://a.example.org/123/456/12345678",
+                        "https://b.example.org/123/456/12345678"
+                      ]
+                    }
+                  }
+                }
+              ],
+              "mirrors": {
+                "tar": {
+                  "ca+https://a.example.org": [
+                    "example.org/_mirrors.json",
+                    "example.org/foo/_mirrors.json"
+                  ],
+                  "ca+https://b.example.org": [
+                    "example.org/_mirrors.json"
+                  ]
+                }
+              }
+            }"#]]
+        .assert_eq(&serde_json::to_string_pretty(&detail).unwrap());
+    }
+}
@@ -0,0 +1,189 @@
+use clap::ValueEnum;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use crate::catalog::Catalog;
+use crate::wares::ResolvedWares;
+
+// how many modules are listed in `topModules`.
+const TOP_MODULES: usize = 5;
+
+/// How `stats` prints its summary.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    Json,
+    Table,
+}
+
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct PacktypeStats {
+    pub items: usize,
+    pub wares: usize,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct ModuleReleases {
+    pub name: String,
+    pub releases: usize,
+}
+
+/// A summary of a catalog, as printed by `stats`.
+#[derive(Debug, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Stats {
+    pub modules: usize,
+    pub releases: usize,
+    pub items: usize,
+    /// Distinct ware IDs.
+    pub wares: usize,
+    pub by_packtype: BTreeMap<String, PacktypeStats>,
+    /// How many wares have a given number of mirror locations.
+    pub mirrors_per_ware: BTreeMap<usize, usize>,
+    /// The modules with the most releases, most first.
+    pub top_modules: Vec<ModuleReleases>,
+    /// Wares which are used by more than one catalog ref.
+    pub shared_wares: usize,
+    /// Distinct ware IDs without a packtype, which are left out of `byPacktype`.
+    pub invalid_wares: usize,
+}
+
+pub fn stats(catalog: &Catalog, resolved: &ResolvedWares) -> Stats {
+    let mut result = Stats {
+        modules: catalog.modules.len(),
+        items: catalog.refs.len(),
+        ..Default::default()
+    };
+    let mut refs_per_ware: BTreeMap<&String, usize> = BTreeMap::new();
+    for ware_id in catalog.refs.values() {
+        *refs_per_ware.entry(ware_id).or_default() += 1;
+        if let Some((pack_type, _)) = ware_id.split_once(':') {
+            let entry = result.by_packtype.entry(pack_type.to_string());
+            entry.or_default().items += 1;
+        }
+    }
+    result.wares = refs_per_ware.len();
+    result.shared_wares = refs_per_ware.values().filter(|n| **n > 1).count();
+    for ware_id in refs_per_ware.keys() {
+        match ware_id.split_once(':') {
+            Some((pack_type, _)) => {
+                let entry = result.by_packtype.entry(pack_type.to_string());
+                entry.or_default().wares += 1;
+            }
+            None => result.invalid_wares += 1,
+        }
+        let mirrors = resolved.get(*ware_id).map_or(0, |urls| urls.len());
+        *result.mirrors_per_ware.entry(mirrors).or_default() += 1;
+    }
+    let mut modules: Vec<ModuleReleases> = catalog
+        .modules
+        .iter()
+        .map(|m| ModuleReleases {
+            name: m.module.name.clone(),
+            releases: m.releases.len(),
+        })
+        .collect();
+    result.releases = modules.iter().map(|m| m.releases).sum();
+    // modules are sorted by name, and the sort is stable, so ties stay in name order.
+    modules.sort_by_key(|m| std::cmp::Reverse(m.releases));
+    modules.truncate(TOP_MODULES);
+    result.top_modules = modules;
+    result
+}
+
+/// Renders `stats` as aligned plain-text tables.
+pub fn table(stats: &Stats) -> String {
+    let mut out = String::new();
+    let rows = [
+        ("modules", stats.modules),
+        ("releases", stats.releases),
+        ("items", stats.items),
+        ("wares", stats.wares),
+        ("shared wares", stats.shared_wares),
+        ("invalid wares", stats.invalid_wares),
+    ];
+    for (name, count) in rows {
+        writeln!(out, "{name:<14}{count:>8}").unwrap();
+    }
+    writeln!(out, "\n{:<14}{:>8}{:>8}", "packtype", "items", "wares").unwrap();
+    for (pack_type, p) in stats.by_packtype.iter() {
+        writeln!(out, "{pack_type:<14}{:>8}{:>8}", p.items, p.wares).unwrap();
+    }
+    writeln!(out, "\n{:<14}{:>8}", "mirrors", "wares").unwrap();
+    for (mirrors, wares) in stats.mirrors_per_ware.iter() {
+        writeln!(out, "{mirrors:<14}{wares:>8}").unwrap();
+    }
+    let width = stats
+        .top_modules
+        .iter()
+        .map(|m| m.name.len())
+        .max()
+        .unwrap_or(0)
+        .max(14);
+    writeln!(out, "\n{:<width$}{:>10}", "module", "releases").unwrap();
+    for m in stats.top_modules.iter() {
+        writeln!(out, "{:<width$}{:>10}", m.name, m.releases).unwrap();
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TempCatalog;
+    use expect_test::expect;
+    use serde_json::json;
+
+    #[test]
+    fn test_stats() {
+        let catalog = TempCatalog::new();
+        catalog.add_module(
+            "example.org/foo",
+            &[
+                ("v1", &[("src", "tar:abcdefgh"), ("repo", "git:0123456789")]),
+                ("v2", &[("src", "tar:abcdefgh")]),
+            ],
+        );
+        catalog.add_module(
+            "example.org/bar",
+            &[("v1", &[("src", "tar:ijklmnop"), ("bin", "nopacktype")])],
+        );
+        catalog.add_mirrors(
+            "example.org",
+            json!({"byModule": {"example.org/foo": {"tar": [
+                "ca+https://a.example.org",
+                "ca+https://b.example.org",
+            ]}}}),
+        );
+        let loaded = Catalog::load(&catalog.path, &Default::default()).unwrap();
+        let resolved = loaded.wares(&Default::default()).unwrap();
+        let stats = stats(&loaded, &resolved);
+        assert_eq!(
+            (stats.modules, stats.releases, stats.items, stats.wares),
+            (2, 3, 5, 4)
+        );
+        assert_eq!(stats.shared_wares, 1);
+        assert_eq!(stats.invalid_wares, 1);
+        expect![[r#"
+            modules              2
+            releases             3
+            items                5
+            wares                4
+            shared wares         1
+            invalid wares        1
+
+            packtype         items   wares
+            git                  1       1
+            tar                  3       2
+
+            mirrors          wares
+            0                    3
+            2                    1
+
+            module           releases
+            example.org/foo         2
+            example.org/bar         1
+        "#]]
+        .assert_eq(&table(&stats));
+    }
+}
@@ -0,0 +1,217 @@
+use rayon::prelude::*;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use url::Url;
+
+use crate::ca_layout::Layout;
+use crate::str_error::StrError; //required by errf macro
+use crate::ware_id;
+use crate::wares::{self, ResolvedWares};
+
+/// The outcome of `sync`, listing ware IDs by what happened to them.
+#[derive(Debug, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncReport {
+    pub downloaded: Vec<String>,
+    pub present: Vec<String>,
+    /// Wares which cannot be stored as a file, with the reason.
+    pub skipped: BTreeMap<String, String>,
+    /// Wares for which every mirror failed, with the error of each attempt.
+    pub failed: BTreeMap<String, Vec<String>>,
+}
+
+enum Outcome {
+    Downloaded,
+    Present,
+    Skipped(String),
+    Failed(Vec<String>),
+}
+
+/// Returns where a ware is stored below `dest`, using the same layout as content-addressable mirrors.
+/// Ware IDs which are malformed or would place the ware outside of `dest` are rejected.
+pub fn store_path(dest: &Path, ware_id: &str) -> Result<PathBuf, Box<dyn Error>> {
+    ware_id::check(ware_id).map_err(|e| errf!("{e}"))?;
+    let (pack_type, ware_hash) = wares::split_ware(ware_id.to_string());
+    let mut path = dest.to_path_buf();
+    for segment in Layout::default().segments(&pack_type, &ware_hash)? {
+        if segment == "." || segment == ".." || segment.contains(['/', '\\']) {
+            return Err(errf!("ware ID is not a safe file name"));
+        }
+        path.push(segment);
+    }
+    Ok(path)
+}
+
+fn download(agent: &ureq::Agent, url: &str, path: &Path) -> Result<(), Box<dyn Error>> {
+    let parsed = Url::parse(url)?;
+    let part = path.with_extension("part");
+    fs::create_dir_all(path.parent().unwrap())?;
+    if parsed.scheme() == "file" {
+        let source = parsed
+            .to_file_path()
+            .map_err(|_| errf!("not a local path: {url}"))?;
+        fs::copy(source, &part)?;
+    } else {
+        // the url is already part of the reported error, so ureq's own errors are shortened.
+        let response = agent.get(url).call().map_err(|e| match e {
+            ureq::Error::Status(code, r) => errf!("HTTP {code} {}", r.status_text()),
+            ureq::Error::Transport(t) => match t.message() {
+                Some(message) => errf!("{}: {message}", t.kind()),
+                None => errf!("{}", t.kind()),
+            },
+        })?;
+        let mut file = fs::File::create(&part)?;
+        io::copy(&mut response.into_reader(), &mut file)?;
+    }
+    fs::rename(&part, path)?;
+    Ok(())
+}
+
+fn sync_one(
+    agent: &ureq::Agent,
+    dest: &Path,
+    ware_id: &str,
+    urls: &[String],
+    attempts: usize,
+) -> Outcome {
+    if ware_id.starts_with("git:") {
+        return Outcome::Skipped(String::from("git wares must be cloned"));
+    }
+    if urls.is_empty() {
+        return Outcome::Skipped(String::from("no mirrors"));
+    }
+    let path = match store_path(dest, ware_id) {
+        Ok(p) => p,
+        Err(e) => return Outcome::Skipped(e.to_string()),
+    };
+    if fs::metadata(&path).map(|m| m.len() > 0).unwrap_or(false) {
+        return Outcome::Present;
+    }
+    let mut errors = Vec::new();
+    // every round tries each mirror once, in order.
+    for _ in 0..attempts.max(1) {
+        for url in urls.iter() {
+            match download(agent, url, &path) {
+                Ok(()) => return Outcome::Downloaded,
+                Err(e) => errors.push(format!("{url}: {e}")),
+            }
+        }
+    }
+    let _ = fs::remove_file(path.with_extension("part"));
+    Outcome::Failed(errors)
+}
+
+/// Downloads every ware into `dest` in the `abc/def/<hash>` layout, so that `dest` can be served
+/// as a `ca+file://` or `ca+https://` mirror. Wares already present are not downloaded again.
+/// Each ware is attempted `attempts` times across all of its mirrors before it is reported as failed.
+pub fn sync(wares: &ResolvedWares, dest: &Path, attempts: usize, timeout: Duration) -> SyncReport {
+    let agent = ureq::AgentBuilder::new().timeout(timeout).build();
+    let entries: Vec<(&String, &Vec<String>)> = wares.iter().collect();
+    let outcomes: Vec<Outcome> = entries
+        .par_iter()
+        .map(|(ware_id, urls)| sync_one(&agent, dest, ware_id, urls, attempts))
+        .collect();
+    let mut report = SyncReport::default();
+    for ((ware_id, _), outcome) in entries.into_iter().zip(outcomes) {
+        let ware_id = ware_id.clone();
+        match outcome {
+            Outcome::Downloaded => report.downloaded.push(ware_id),
+            Outcome::Present => report.present.push(ware_id),
+            Outcome::Skipped(reason) => {
+                report.skipped.insert(ware_id, reason);
+            }
+            Outcome::Failed(errors) => {
+                report.failed.insert(ware_id, errors);
+            }
+        }
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{StandIn, TempCatalog};
+
+    const WARE: &str = "tar:5K7rekQyv4YJphfwfssRsLqHtrL4G9bVmCuarnJyvNaCWzABt6ujLvRRQ48ppRqvNZ";
+    const MISSING: &str = "tar:12KZBfkSbUHo9JJojPY7HHNP2FMobgTzoefARF2JkBo6KWj2E1mTquL4pfTAKtdfe6";
+
+    fn wares(entries: &[(&str, Vec<String>)]) -> ResolvedWares {
+        entries
+            .iter()
+            .map(|(w, urls)| (w.to_string(), urls.clone()))
+            .collect()
+    }
+
+    // the URL path of a ware on a mirror at "/m".
+    fn served(ware_id: &str) -> String {
+        let path = store_path(Path::new("/m"), ware_id).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn test_sync() {
+        let stand_in = StandIn::with_files(vec![(&served(WARE), "ware bytes")]);
+        let dest = TempCatalog::new();
+        let wares = wares(&[
+            (
+                WARE,
+                // the first mirror answers 404, so the ware must come from the fallback.
+                vec![
+                    format!("{}/a{}", stand_in.url, served(WARE)),
+                    format!("{}{}", stand_in.url, served(WARE)),
+                ],
+            ),
+            (
+                MISSING,
+                vec![format!("{}{}", stand_in.url, served(MISSING))],
+            ),
+            ("git:0123456789", vec![String::from("https://example.org")]),
+            (
+                "oci:abc/../../../../etc",
+                vec![String::from("https://example.org")],
+            ),
+            ("nopacktype", vec![String::from("https://example.org")]),
+        ]);
+
+        let report = sync(&wares, &dest.path, 2, Duration::from_secs(5));
+        assert_eq!(report.downloaded, vec![WARE]);
+        assert_eq!(report.failed[MISSING].len(), 2);
+        assert_eq!(report.skipped["git:0123456789"], "git wares must be cloned");
+        assert_eq!(
+            report.skipped["oci:abc/../../../../etc"],
+            "ware ID is not a safe file name"
+        );
+        assert_eq!(report.skipped["nopacktype"], "ware ID has no packtype");
+        let stored = store_path(&dest.path, WARE).unwrap();
+        assert_eq!(fs::read_to_string(&stored).unwrap(), "ware bytes");
+
+        let report = sync(&wares, &dest.path, 1, Duration::from_secs(5));
+        assert_eq!(report.present, vec![WARE]);
+        assert!(report.downloaded.is_empty());
+    }
+
+    #[test]
+    fn test_sync_from_file_mirror() {
+        let source = TempCatalog::new();
+        let source_path = store_path(&source.path, WARE).unwrap();
+        fs::create_dir_all(source_path.parent().unwrap()).unwrap();
+        fs::write(&source_path, "local bytes").unwrap();
+        let dest = TempCatalog::new();
+        let url = Url::from_file_path(source_path).unwrap();
+        let report = sync(
+            &wares(&[(WARE, vec![url.to_string()])]),
+            &dest.path,
+            1,
+            Duration::from_secs(5),
+        );
+        assert_eq!(report.downloaded, vec![WARE]);
+        let stored = store_path(&dest.path, WARE).unwrap();
+        assert_eq!(fs::read_to_string(stored).unwrap(), "local bytes");
+    }
+}
@@ -0,0 +1,153 @@
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// A catalog directory under the system temp dir which is removed when dropped.
+pub struct TempCatalog {
+    pub path: PathBuf,
+}
+
+impl TempCatalog {
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        let n = COUNTER.fetch_add(1, Ordering::SeqCst);
+        let path =
+            std::env::temp_dir().join(format!("catalog-manifest-test-{}-{n}", std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        TempCatalog { path }
+    }
+
+    /// Writes `contents` to `rel_path` inside the catalog, creating parent directories.
+    pub fn write(&self, rel_path: &str, contents: &str) -> PathBuf {
+        let path = self.path.join(rel_path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    /// Writes a module with the given releases, each a list of (item, ware ID) pairs.
+    pub fn add_module(&self, name: &str, releases: &[(&str, &[(&str, &str)])]) {
+        let release_names: serde_json::Map<String, serde_json::Value> = releases
+            .iter()
+            .map(|(r, _)| (r.to_string(), "zM5Kexample".into()))
+            .collect();
+        let module = serde_json::json!({
+            "catalogmodule.v1": {"name": name, "releases": release_names, "metadata": {}}
+        });
+        self.write(&format!("{name}/_module.json"), &module.to_string());
+        for (release, items) in releases {
+            let items: serde_json::Map<String, serde_json::Value> = items
+                .iter()
+                .map(|(i, w)| (i.to_string(), w.to_string().into()))
+                .collect();
+            let doc = serde_json::json!({"releaseName": release, "items": items, "metadata": {}});
+            self.write(
+                &format!("{name}/_releases/{release}.json"),
+                &doc.to_string(),
+            );
+        }
+    }
+
+    /// Writes a `_mirrors.json` into `rel_dir` from the body of a catalogmirrors.v1 capsule.
+    pub fn add_mirrors(&self, rel_dir: &str, body: serde_json::Value) {
+        let doc = serde_json::json!({ "catalogmirrors.v1": body });
+        self.write(&format!("{rel_dir}/_mirrors.json"), &doc.to_string());
+    }
+}
+
+impl Drop for TempCatalog {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+/// Builds a catalog with `modules` modules spread over a few namespaces,
+/// each with `releases` releases of two tar items, and one `_mirrors.json` per namespace.
+pub fn synthetic_catalog(modules: usize, releases: usize) -> TempCatalog {
+    let catalog = TempCatalog::new();
+    for m in 0..modules {
+        let namespace = format!("ns{}.org", m % 8);
+        let name = format!("{namespace}/module{m}");
+        let wares: Vec<(String, Vec<(&str, String)>)> = (0..releases)
+            .map(|r| {
+                let items = vec![
+                    (
+                        "src",
+                        format!("tar:{m:08}src{r:08}abcdefghijklmnopqrstuvwxyz"),
+                    ),
+                    (
+                        "amd64",
+                        format!("tar:{m:08}bin{r:08}abcdefghijklmnopqrstuvwxyz"),
+                    ),
+                ];
+                (format!("v{r}.0"), items)
+            })
+            .collect();
+        let items: Vec<Vec<(&str, &str)>> = wares
+            .iter()
+            .map(|(_, items)| items.iter().map(|(i, w)| (*i, w.as_str())).collect())
+            .collect();
+        let releases: Vec<(&str, &[(&str, &str)])> = wares
+            .iter()
+            .zip(items.iter())
+            .map(|((r, _), items)| (r.as_str(), items.as_slice()))
+            .collect();
+        catalog.add_module(&name, &releases);
+        if m < 8 {
+            let mut by_module = serde_json::Map::new();
+            for n in (m..modules).step_by(8) {
+                by_module.insert(
+                    format!("{namespace}/module{n}"),
+                    serde_json::json!({"tar": ["ca+https://example.org/wares"]}),
+                );
+            }
+            catalog.add_mirrors(&namespace, serde_json::json!({ "byModule": by_module }));
+        }
+    }
+    catalog
+}
+
+/// A local HTTP server standing in for a mirror. It answers every request with `handler`
+/// until it is dropped.
+pub struct StandIn {
+    pub url: String,
+    server: std::sync::Arc<tiny_http::Server>,
+}
+
+impl StandIn {
+    pub fn new(
+        handler: impl Fn(&tiny_http::Request) -> tiny_http::ResponseBox + Send + 'static,
+    ) -> StandIn {
+        let server = std::sync::Arc::new(tiny_http::Server::http("127.0.0.1:0").unwrap());
+        let url = format!("http://{}", server.server_addr());
+        let worker = std::sync::Arc::clone(&server);
+        std::thread::spawn(move || {
+            for request in worker.incoming_requests() {
+                let response = handler(&request);
+                let _ = request.respond(response);
+            }
+        });
+        StandIn { url, server }
+    }
+
+    /// A stand-in serving `files` by URL path, answering 404 for anything else.
+    pub fn with_files(files: Vec<(&str, &str)>) -> StandIn {
+        let files: std::collections::HashMap<String, String> = files
+            .into_iter()
+            .map(|(p, c)| (p.to_string(), c.to_string()))
+            .collect();
+        StandIn::new(move |request| match files.get(request.url()) {
+            Some(body) => tiny_http::Response::from_string(body.clone()).boxed(),
+            None => tiny_http::Response::empty(404).boxed(),
+        })
+    }
+}
+
+impl Drop for StandIn {
+    fn drop(&mut self) {
+        self.server.unblock();
+    }
+}
@@ -0,0 +1,374 @@
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+use crate::catalog_mirrors::{CatalogMirrorsCapsule, CAPSULE_V1};
+use crate::catalog_releases::ReleaseItem;
+use crate::policy::{MirrorKind, Policy};
+use crate::releases::{self, Module};
+use crate::schema::json_pointer;
+use crate::str_error::StrError; //required by errf macro
+use crate::ware_id;
+
+/// A problem found by `validate`.
+/// `location` is the name of a catalog file, or a catalog file followed by `#` and a JSON
+/// pointer into it.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Violation {
+    pub location: String,
+    pub message: String,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.location, self.message)
+    }
+}
+
+/// Checks every byWare and byModule mirror URL against `policy`. `mirror_files` are the
+/// `_mirrors.json` files of the catalog at `root` as returned by `mirrors::collect_files`.
+pub fn check_mirrors(
+    root: &Path,
+    mirror_files: &[(PathBuf, CatalogMirrorsCapsule)],
+    policy: &Policy,
+) -> Vec<Violation> {
+    let mut result = Vec::new();
+    for (path, capsule) in mirror_files {
+        let file = path.strip_prefix(root).unwrap_or(path).display();
+        let CatalogMirrorsCapsule::V1(mirrors) = capsule else {
+            result.push(Violation {
+                location: file.to_string(),
+                message: String::from("unknown mirrors capsule version"),
+            });
+            continue;
+        };
+        let mut check = |kind, pointer: String, url: &str| {
+            for message in policy.check(kind, url) {
+                result.push(Violation {
+                    location: format!("{file}#{pointer}"),
+                    message: format!("{url}: {message}"),
+                });
+            }
+        };
+        for (ware_id, urls) in mirrors.by_ware.iter() {
+            for url in urls.iter() {
+                let pointer = json_pointer(&[CAPSULE_V1, "byWare", ware_id]);
+                check(MirrorKind::ByWare, pointer, url);
+            }
+        }
+        for (module, by_pack_type) in mirrors.by_module.iter() {
+            for (pack_type, urls) in by_pack_type.iter() {
+                for url in urls.iter() {
+                    let pointer = json_pointer(&[CAPSULE_V1, "byModule", module, pack_type]);
+                    check(MirrorKind::ByModule, pointer, url);
+                }
+            }
+        }
+    }
+    result
+}
+
+/// Checks the syntax of every ware ID in release files and in the byWare mirrors of
+/// `mirror_files`. Locations are relative to `root`.
+pub fn check_ware_ids(
+    root: &Path,
+    modules: &[Module],
+    mirror_files: &[(PathBuf, CatalogMirrorsCapsule)],
+) -> Vec<Violation> {
+    let mut result = Vec::new();
+    for m in modules {
+        for release in m.releases.iter() {
+            let file = release_file(root, m, &release.name);
+            for (item, ware_id) in release.items.iter() {
+                if let Err(message) = ware_id::check(ware_id) {
+                    result.push(Violation {
+                        location: format!("{}#{}", file.display(), json_pointer(&["items", item])),
+                        message: format!("{ware_id}: {message}"),
+                    });
+                }
+            }
+        }
+    }
+    for (path, capsule) in mirror_files {
+        let CatalogMirrorsCapsule::V1(mirrors) = capsule else {
+            continue;
+        };
+        let file = path.strip_prefix(root).unwrap_or(path);
+        for ware_id in mirrors.by_ware.keys() {
+            if let Err(message) = ware_id::check(ware_id) {
+                let pointer = json_pointer(&[CAPSULE_V1, "byWare", ware_id]);
+                result.push(Violation {
+                    location: format!("{}#{pointer}", file.display()),
+                    message,
+                });
+            }
+        }
+    }
+    result
+}
+
+// the module name implied by the directory of `module`, e.g. "warpsys.org/bash".
+fn expected_name(root: &Path, module: &Module) -> String {
+    let dir = module.path.strip_prefix(root).unwrap_or(&module.path);
+    let segments: Vec<_> = dir
+        .components()
+        .filter_map(|c| match c {
+            Component::Normal(s) => Some(s.to_string_lossy()),
+            _ => None,
+        })
+        .collect();
+    segments.join("/")
+}
+
+fn module_file(root: &Path, module: &Module) -> PathBuf {
+    let dir = module.path.strip_prefix(root).unwrap_or(&module.path);
+    dir.join(releases::MODULE_FILE_NAME)
+}
+
+fn release_file(root: &Path, module: &Module, release: &str) -> PathBuf {
+    let dir = module.path.strip_prefix(root).unwrap_or(&module.path);
+    dir.join(releases::MODULE_RELEASES_DIR_NAME)
+        .join(format!("{release}.json"))
+}
+
+/// Checks that the name of every module is its directory relative to `root`.
+pub fn check_module_names(root: &Path, modules: &[Module]) -> Vec<Violation> {
+    let mut result = Vec::new();
+    for m in modules {
+        let expected = expected_name(root, m);
+        if m.module.name != expected {
+            result.push(Violation {
+                location: module_file(root, m).display().to_string(),
+                message: format!(
+                    r#"module name "{}" does not match its directory "{expected}""#,
+                    m.module.name
+                ),
+            });
+        }
+    }
+    result
+}
+
+/// Reports every catalog ref declared by more than one release file, as happens when two modules
+/// have the same name. Modules are visited by path and the first declaration is not reported.
+pub fn check_refs(root: &Path, modules: &[Module]) -> Vec<Violation> {
+    let mut modules: Vec<&Module> = modules.iter().collect();
+    modules.sort_by(|a, b| a.path.cmp(&b.path));
+    let mut declared: BTreeMap<String, PathBuf> = BTreeMap::new();
+    let mut result = Vec::new();
+    for m in modules {
+        for release in m.releases.iter() {
+            let file = release_file(root, m, &release.name);
+            for item in release.items.keys() {
+                let catalog_ref = ReleaseItem {
+                    module: m.module.name.clone(),
+                    version: release.name.clone(),
+                    name: item.clone(),
+                }
+                .to_string();
+                match declared.get(&catalog_ref) {
+                    Some(first) => result.push(Violation {
+                        location: format!("{}#{}", file.display(), json_pointer(&["items", item])),
+                        message: format!(
+                            "duplicate catalog ref {catalog_ref}, first declared in {}",
+                            first.display()
+                        ),
+                    }),
+                    None => {
+                        declared.insert(catalog_ref, file.clone());
+                    }
+                }
+            }
+        }
+    }
+    result
+}
+
+/// A module renamed by `fix_module_names`.
+pub struct Renamed {
+    pub path: PathBuf,
+    pub from: String,
+    pub to: String,
+}
+
+/// Sets the name in the `_module.json` of every module whose name does not match its
+/// directory to the directory. Other fields are kept as they are.
+/// byModule mirrors are keyed by module name and are not changed, so a warning is printed
+/// for each renamed module which has any in `mirror_files`.
+pub fn fix_module_names(
+    root: &Path,
+    modules: &[Module],
+    mirror_files: &[(PathBuf, CatalogMirrorsCapsule)],
+) -> Result<Vec<Renamed>, Box<dyn Error>> {
+    let mut result = Vec::new();
+    for m in modules {
+        let expected = expected_name(root, m);
+        if m.module.name == expected {
+            continue;
+        }
+        let path = m.path.join(releases::MODULE_FILE_NAME);
+        let contents = fs::read_to_string(&path)?;
+        let mut doc: Value =
+            serde_json::from_str(&contents).map_err(|e| errf!("{}: {e}", path.display()))?;
+        let body = doc
+            .as_object_mut()
+            .and_then(|capsule| capsule.values_mut().next())
+            .and_then(|body| body.as_object_mut())
+            .ok_or_else(|| -> Box<dyn Error> {
+                errf!("{}: malformed module capsule", path.display())
+            })?;
+        body.insert(String::from("name"), Value::String(expected.clone()));
+        let mut output = serde_json::to_string_pretty(&doc)?;
+        output.push('\n');
+        fs::write(&path, output)?;
+        let has_mirrors = mirror_files.iter().any(|(_, capsule)| match capsule {
+            CatalogMirrorsCapsule::V1(mirrors) => mirrors.by_module.contains_key(&m.module.name),
+            CatalogMirrorsCapsule::Unknown { .. } => false,
+        });
+        if has_mirrors {
+            eprintln!(
+                r#"WARNING: byModule mirrors of "{}" are not renamed to "{expected}""#,
+                m.module.name
+            );
+        }
+        result.push(Renamed {
+            path: module_file(root, m),
+            from: m.module.name.clone(),
+            to: expected,
+        });
+    }
+    Ok(result)
+}
+
+/// Runs every check of the `validate` command on the catalog at `root`, whose modules are
+/// `modules` as returned by `releases::collect_modules` and whose `_mirrors.json` files are
+/// `mirror_files`. Unlike loading a `Catalog`, this does not stop at duplicate catalog refs.
+pub fn validate(
+    root: &Path,
+    modules: &[Module],
+    mirror_files: &[(PathBuf, CatalogMirrorsCapsule)],
+    policy: &Policy,
+) -> Vec<Violation> {
+    let mut result = check_module_names(root, modules);
+    result.extend(check_refs(root, modules));
+    result.extend(check_ware_ids(root, modules, mirror_files));
+    result.extend(check_mirrors(root, mirror_files, policy));
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::catalog::Catalog;
+    use crate::mirrors;
+    use crate::testutil::TempCatalog;
+    use serde_json::json;
+
+    #[test]
+    fn test_validate() {
+        let catalog = TempCatalog::new();
+        catalog.add_module("example.org/foo", &[("v1", &[("src", "tar:abcdefgh")])]);
+        catalog.add_module("example.org/bar", &[]);
+        catalog.write(
+            "example.org/bar/_module.json",
+            r#"{"catalogmodule.v1": {"name": "example.org/baz", "releases": {}, "metadata": {}}}"#,
+        );
+        catalog.add_mirrors(
+            "example.org",
+            json!({
+                "byWare": {"tar:abcdefgh": ["http://mirror.example.org/foo.tgz"]},
+                "byModule": {"example.org/foo": {"tar": ["ca+https://mirror.example.org"]}}
+            }),
+        );
+        catalog.add_mirrors(
+            "example.org/foo",
+            json!({"byModule": {"example.org/foo": {"tar": ["ca+ftp://mirror.example.org"]}}}),
+        );
+        let opts = Default::default();
+        let loaded = releases::collect_modules(&catalog.path, &opts).unwrap();
+        let files = mirrors::collect_files(&catalog.path, &opts).unwrap();
+        let violations: Vec<String> = validate(&catalog.path, &loaded, &files, &Policy::default())
+            .iter()
+            .map(|v| v.to_string())
+            .collect();
+        assert_eq!(
+            violations,
+            vec![
+                r#"example.org/bar/_module.json: module name "example.org/baz" does not match its directory "example.org/bar""#,
+                "example.org/foo/_releases/v1.json#/items/src: tar:abcdefgh: tar hash decodes to 6 bytes, expected a 48 byte SHA-384 digest",
+                "example.org/_mirrors.json#/catalogmirrors.v1/byWare/tar:abcdefgh: tar hash decodes to 6 bytes, expected a 48 byte SHA-384 digest",
+                "example.org/_mirrors.json#/catalogmirrors.v1/byWare/tar:abcdefgh: http://mirror.example.org/foo.tgz: scheme http is not allowed",
+                "example.org/foo/_mirrors.json#/catalogmirrors.v1/byModule/example.org~1foo/tar: ca+ftp://mirror.example.org: scheme ftp is not allowed",
+            ]
+        );
+        let renamed = fix_module_names(&catalog.path, &loaded, &files).unwrap();
+        assert_eq!(renamed.len(), 1);
+        assert_eq!(
+            (renamed[0].from.as_str(), renamed[0].to.as_str()),
+            ("example.org/baz", "example.org/bar")
+        );
+        let loaded = releases::collect_modules(&catalog.path, &opts).unwrap();
+        assert!(check_module_names(&catalog.path, &loaded).is_empty());
+    }
+
+    #[test]
+    fn test_check_module_names_nested() {
+        let catalog = TempCatalog::new();
+        catalog.add_module("example.org/foo", &[]);
+        catalog.add_module("example.org/foo/bar", &[]);
+        catalog.write(
+            "example.org/foo/bar/_module.json",
+            r#"{"catalogmodule.v1": {"name": "example.org/bar", "releases": {}, "metadata": {}}}"#,
+        );
+        let loaded = releases::collect_modules(&catalog.path, &Default::default()).unwrap();
+        let violations: Vec<String> = check_module_names(&catalog.path, &loaded)
+            .iter()
+            .map(|v| v.to_string())
+            .collect();
+        assert_eq!(
+            violations,
+            vec![
+                r#"example.org/foo/bar/_module.json: module name "example.org/bar" does not match its directory "example.org/foo/bar""#
+            ]
+        );
+        let renamed = fix_module_names(&catalog.path, &loaded, &[]).unwrap();
+        assert_eq!(renamed[0].to, "example.org/foo/bar");
+    }
+
+    #[test]
+    fn test_duplicate_module_names() {
+        let catalog = TempCatalog::new();
+        catalog.add_module("example.org/foo", &[("v1", &[("src", "tar:abcdefgh")])]);
+        // a copy of foo which was not renamed.
+        catalog.add_module("example.org/bar", &[("v1", &[("src", "tar:abcdefgh")])]);
+        catalog.write(
+            "example.org/bar/_module.json",
+            r#"{"catalogmodule.v1": {"name": "example.org/foo", "releases": {"v1": "zM5Kexample"}, "metadata": {}}}"#,
+        );
+        let policy = Policy::default();
+        let opts = Default::default();
+        assert!(Catalog::load(&catalog.path, &opts).is_err());
+        let loaded = releases::collect_modules(&catalog.path, &opts).unwrap();
+        let violations: Vec<String> = validate(&catalog.path, &loaded, &[], &policy)
+            .iter()
+            .filter(|v| !v.message.contains("tar hash"))
+            .map(|v| v.to_string())
+            .collect();
+        assert_eq!(
+            violations,
+            vec![
+                r#"example.org/bar/_module.json: module name "example.org/foo" does not match its directory "example.org/bar""#,
+                "example.org/foo/_releases/v1.json#/items/src: duplicate catalog ref example.org/foo:v1:src, first declared in example.org/bar/_releases/v1.json",
+            ]
+        );
+        fix_module_names(&catalog.path, &loaded, &[]).unwrap();
+        let loaded = releases::collect_modules(&catalog.path, &opts).unwrap();
+        assert!(check_module_names(&catalog.path, &loaded).is_empty());
+        assert!(check_refs(&catalog.path, &loaded).is_empty());
+        assert!(Catalog::load(&catalog.path, &opts).is_ok());
+    }
+}
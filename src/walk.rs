@@ -0,0 +1,173 @@
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::cache::Cache;
+use crate::ignore::Ignore;
+use crate::releases::{MODULE_FILE_NAME, MODULE_RELEASES_DIR_NAME};
+use crate::schema::Schema;
+
+/// Errors are Send + Sync so that they can cross the thread pool used by the walkers.
+pub type WalkError = Box<dyn Error + Send + Sync>;
+
+/// Settings shared by the catalog walkers in `releases` and `mirrors`.
+#[derive(Default)]
+pub struct WalkOptions {
+    pub schema: Schema,
+    pub cache: Cache,
+    pub ignore: Ignore,
+    /// Descend into symlinked directories and read symlinked files.
+    pub follow_symlinks: bool,
+}
+
+/// The entries of a catalog directory which the walkers visit, in directory order.
+#[derive(Debug, Default)]
+pub struct DirEntries {
+    pub dirs: Vec<PathBuf>,
+    pub files: Vec<PathBuf>,
+}
+
+/// Lists the sub-directories and regular files of `dir_path`.
+/// Every walker descends through these, so they agree on which directories are part of the
+/// catalog: all of them, including directories below a module, which may hold nested modules.
+/// Left out are the `_releases` directory of a module, as it holds nothing but release files,
+/// paths skipped by `opts.ignore`, and symlinks unless `opts.follow_symlinks` is set.
+/// A symlinked `_module.json` is always listed.
+/// A symlink to a directory which is being walked already is skipped with a warning.
+pub fn read_dir(dir_path: &Path, opts: &WalkOptions) -> Result<DirEntries, WalkError> {
+    let mut result = DirEntries::default();
+    for entry in fs::read_dir(dir_path)? {
+        let entry = entry?;
+        let path = entry.path();
+        let mut file_type = entry.file_type()?;
+        if file_type.is_symlink() {
+            if !opts.follow_symlinks && entry.file_name() != MODULE_FILE_NAME {
+                continue;
+            }
+            file_type = match fs::metadata(&path) {
+                Ok(m) => m.file_type(),
+                Err(e) => {
+                    eprintln!(
+                        "WARNING: skipping broken symlink \"{}\": {e}",
+                        path.display()
+                    );
+                    continue;
+                }
+            };
+            if file_type.is_dir() && is_cycle(dir_path, &path)? {
+                eprintln!(
+                    r#"WARNING: not following symlink "{}" to a directory which contains it"#,
+                    path.display()
+                );
+                continue;
+            }
+        }
+        if opts.ignore.is_ignored(&path, file_type.is_dir()) {
+            continue;
+        }
+        if file_type.is_dir() {
+            result.dirs.push(path);
+        } else if file_type.is_file() {
+            result.files.push(path);
+        }
+    }
+    let is_module = result
+        .files
+        .iter()
+        .any(|f| f.file_name().is_some_and(|n| n == MODULE_FILE_NAME));
+    if is_module {
+        result
+            .dirs
+            .retain(|d| d.file_name().is_none_or(|n| n != MODULE_RELEASES_DIR_NAME));
+    }
+    Ok(result)
+}
+
+// a symlinked directory leads into a cycle if it is the same directory as `dir_path`
+// or one of the directories it was reached through.
+fn is_cycle(dir_path: &Path, link: &Path) -> Result<bool, WalkError> {
+    let target = fs::canonicalize(link)?;
+    for ancestor in dir_path.ancestors() {
+        if ancestor.as_os_str().is_empty() {
+            break;
+        }
+        if fs::canonicalize(ancestor)? == target {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::catalog::Catalog;
+    use crate::testutil::TempCatalog;
+    use serde_json::json;
+    #[cfg(unix)]
+    use std::os::unix::fs::symlink;
+
+    #[cfg(unix)]
+    #[test]
+    fn test_skip_rules() {
+        let catalog = TempCatalog::new();
+        catalog.add_module("example.org/foo", &[("v1", &[("src", "tar:abcdefgh")])]);
+        let stray = json!({"byWare": {"tar:stray000": ["https://example.org/stray"]}});
+        catalog.add_mirrors(".git/objects", stray.clone());
+        catalog.add_mirrors("example.org/drafts", stray);
+        catalog.write(".catalogignore", "drafts/\n");
+        fs::create_dir_all(catalog.path.join("linked")).unwrap();
+        symlink(
+            catalog.path.join("example.org/foo"),
+            catalog.path.join("linked/foo"),
+        )
+        .unwrap();
+        symlink(&catalog.path, catalog.path.join("example.org/loop")).unwrap();
+
+        let load = |follow_symlinks| {
+            let opts = WalkOptions {
+                ignore: Ignore::load(&catalog.path).unwrap(),
+                follow_symlinks,
+                ..Default::default()
+            };
+            Catalog::load(&catalog.path, &opts)
+        };
+        let loaded = load(false).unwrap();
+        assert_eq!(loaded.modules.len(), 1);
+        assert_eq!(
+            serde_json::to_value(&loaded.mirrors).unwrap(),
+            json!({"catalogmirrors.v1": {"byWare": {}, "byModule": {}}})
+        );
+        // the loop is skipped, while the linked module is found a second time.
+        let err = load(true).unwrap_err();
+        assert!(err.to_string().contains("duplicate catalog ref"), "{err}");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_symlinked_files() {
+        let catalog = TempCatalog::new();
+        catalog.add_module("example.org/foo", &[("v1", &[("src", "tar:abcdefgh")])]);
+        let module_file = catalog.path.join("example.org/foo/_module.json");
+        fs::rename(&module_file, catalog.path.join("foo.json")).unwrap();
+        symlink(catalog.path.join("foo.json"), &module_file).unwrap();
+        let load = |follow_symlinks| {
+            let opts = WalkOptions {
+                follow_symlinks,
+                ..Default::default()
+            };
+            Catalog::load(&catalog.path, &opts)
+        };
+        // a symlinked module file is read without --follow-symlinks.
+        assert_eq!(load(false).unwrap().modules.len(), 1);
+
+        catalog.add_module("example.org/bar", &[("v1", &[("src", "tar:ijklmnop")])]);
+        let release_file = catalog.path.join("example.org/bar/_releases/v1.json");
+        fs::rename(&release_file, catalog.path.join("bar-v1.json")).unwrap();
+        symlink(catalog.path.join("bar-v1.json"), &release_file).unwrap();
+        let err = load(false).unwrap_err();
+        assert!(err.to_string().contains("non-regular file"), "{err}");
+        let loaded = load(true).unwrap();
+        assert_eq!(loaded.module("example.org/bar").unwrap().releases.len(), 1);
+    }
+}
@@ -0,0 +1,101 @@
+const BASE58_ALPHABET: &str = "123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+
+// the SHA-384 digest length, and its multihash code and prefix.
+const SHA384_LEN: usize = 48;
+const SHA384_PREFIX: [u8; 2] = [0x20, 0x30];
+
+/// Checks the syntax of a ware ID for the packtypes it knows: `tar` wares are a base58
+/// SHA-384 digest, either bare as warpforge writes them or as a sha2-384 multihash, and
+/// `git` wares are a 40 or 64 digit hex commit. Other packtypes are only checked for a hash.
+pub fn check(ware_id: &str) -> Result<(), String> {
+    let Some((pack_type, hash)) = ware_id.split_once(':') else {
+        return Err(String::from("ware ID has no packtype"));
+    };
+    if hash.is_empty() {
+        return Err(String::from("ware ID has no hash"));
+    }
+    match pack_type {
+        "tar" => {
+            let bytes = base58_decode(hash)?;
+            let well_formed = bytes.len() == SHA384_LEN
+                || (bytes.len() == SHA384_PREFIX.len() + SHA384_LEN
+                    && bytes.starts_with(&SHA384_PREFIX));
+            if !well_formed {
+                return Err(format!(
+                    "tar hash decodes to {} bytes, expected a {SHA384_LEN} byte SHA-384 digest",
+                    bytes.len()
+                ));
+            }
+        }
+        "git" if !matches!(hash.len(), 40 | 64) || !hash.chars().all(|c| c.is_ascii_hexdigit()) => {
+            return Err(String::from("git hash is not a 40 or 64 digit hex commit"));
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+fn base58_decode(s: &str) -> Result<Vec<u8>, String> {
+    // little-endian while decoding.
+    let mut bytes: Vec<u8> = Vec::new();
+    for c in s.chars() {
+        let digit = BASE58_ALPHABET
+            .find(c)
+            .ok_or_else(|| format!("invalid base58 character {c:?}"))?;
+        let mut carry = digit as u32;
+        for b in bytes.iter_mut() {
+            carry += *b as u32 * 58;
+            *b = carry as u8;
+            carry >>= 8;
+        }
+        while carry > 0 {
+            bytes.push(carry as u8);
+            carry >>= 8;
+        }
+    }
+    // each leading "1" is a zero byte.
+    let zeros = s.chars().take_while(|c| *c == '1').count();
+    bytes.extend(std::iter::repeat_n(0, zeros));
+    bytes.reverse();
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check() {
+        let cases = vec![
+            (
+                "tar:5K7rekQyv4YJphfwfssRsLqHtrL4G9bVmCuarnJyvNaCWzABt6ujLvRRQ48ppRqvNZ",
+                Ok(()),
+            ),
+            (
+                "tar:12KZBfkSbUHo9JJojPY7HHNP2FMobgTzoefARF2JkBo6KWj2E1mTquL4pfTAKtdfe6",
+                Ok(()),
+            ),
+            // the first ware as a multihash
+            (
+                "tar:Q1CpbPq13nneAR18yftpoLZQNSkKDQDNGxVQcQL78iZzRkKFiTzp7GrjV1UwspJPMBp9",
+                Ok(()),
+            ),
+            (
+                "tar:5K7rekQyv4YJphfwfssRsLqHtrL4G9bVmCuarnJyvNaCWzABt6ujLvRRQ48ppRqv",
+                Err("tar hash decodes to 47 bytes, expected a 48 byte SHA-384 digest"),
+            ),
+            ("tar:abc0def", Err(r#"invalid base58 character '0'"#)),
+            ("git:0123456789abcdef0123456789abcdef01234567", Ok(())),
+            (
+                "git:0123456789",
+                Err("git hash is not a 40 or 64 digit hex commit"),
+            ),
+            ("oci:anything", Ok(())),
+            ("tar:", Err("ware ID has no hash")),
+            ("abcdefgh", Err("ware ID has no packtype")),
+        ];
+        for (ware_id, expected) in cases {
+            assert_eq!(check(ware_id), expected.map_err(String::from), "{ware_id}");
+        }
+    }
+}
@@ -1,18 +1,37 @@
 use indexmap::IndexMap;
 use std::collections::BTreeMap;
-use std::collections::BTreeSet;
 use std::error::Error;
-use std::path::PathBuf;
+use std::path::Path;
 use url::Url;
 
+use crate::ca_layout::Layout;
 use crate::catalog_mirrors::{CatalogMirrors, CatalogMirrorsCapsule};
+use crate::packtype::{ResolveContext, Resolvers};
 use crate::str_error::StrError;
+use crate::walk::WalkOptions;
 use crate::{mirrors, releases};
 
-pub fn resolve_all(dir: &PathBuf) -> Result<BTreeMap<String, BTreeSet<String>>, Box<dyn Error>> {
-    let mirror_data = mirrors::collect(dir)?;
-    let release_data = releases::collect(dir)?;
-    let result = join(mirror_data, release_data)?;
+/// Ware IDs and their mirror locations, most preferred first.
+pub type ResolvedWares = BTreeMap<String, Vec<String>>;
+
+/// How wares are resolved to mirror locations.
+#[derive(Default)]
+pub struct ResolveOptions {
+    pub resolvers: Resolvers,
+    /// Mirror priorities, keyed by mirror URL or a prefix of it.
+    pub priorities: IndexMap<String, i64>,
+    /// The layouts of content-addressable mirrors, keyed by mirror URL without fragment.
+    pub layouts: IndexMap<String, String>,
+}
+
+pub fn resolve_all(
+    dir: &Path,
+    opts: &WalkOptions,
+    resolve: &ResolveOptions,
+) -> Result<ResolvedWares, Box<dyn Error>> {
+    let mirror_data = mirrors::collect(dir, opts)?;
+    let release_data = releases::collect(dir, opts)?;
+    let result = join(mirror_data, release_data, resolve)?;
     Ok(result)
 }
 
@@ -21,24 +40,49 @@ fn split_release(release_id: String) -> (String, String, String) {
     if v.len() < 3 {
         panic!(r#"expected release id "{release_id}" to have three parts"#);
     };
-    return (String::from(v[0]), String::from(v[1]), String::from(v[2]));
+    (String::from(v[0]), String::from(v[1]), String::from(v[2]))
 }
 
-fn split_ware(ware_id: String) -> (String, String) {
+pub fn split_ware(ware_id: String) -> (String, String) {
     let v: Vec<&str> = ware_id.splitn(2, ':').collect();
     if v.len() < 2 {
         eprintln!("vector: {:?}", v);
         panic!(r#"expected ware id "{ware_id}" to have two parts"#);
     }
-    return (String::from(v[0]), String::from(v[1]));
+    (String::from(v[0]), String::from(v[1]))
+}
+
+// the layout of a content-addressable mirror: a "layout" fragment on the mirror URL wins over
+// the layouts of the config file, which are keyed by the mirror URL without fragment.
+fn mirror_layout(
+    mirror: &str,
+    base: &Url,
+    layouts: &IndexMap<String, String>,
+) -> Result<Layout, Box<dyn Error>> {
+    if let Some(fragment) = base.fragment() {
+        return match fragment.strip_prefix("layout=") {
+            Some(layout) => layout.parse(),
+            None => Err(errf!(r#"unknown mirror fragment "{fragment}""#)),
+        };
+    }
+    let key = mirror.split('#').next().unwrap_or(mirror);
+    match layouts.get(key) {
+        Some(layout) => layout.parse(),
+        None => Ok(Layout::default()),
+    }
 }
 
 // returns None if mirror is not a content-addressable link.
 // otherwise returns the fully-qualified link to the ware for the content-addressable mirror.
-fn resolve_ca_link(mirror: String, ware_hash: String) -> Result<Option<String>, Box<dyn Error>> {
+pub fn resolve_ca_link(
+    mirror: String,
+    pack_type: &str,
+    ware_hash: String,
+    layouts: &IndexMap<String, String>,
+) -> Result<Option<String>, Box<dyn Error>> {
     let base = Url::parse(mirror.as_str())?;
     let mut mir_url = base.clone();
-    let mut scheme = (&base).scheme();
+    let mut scheme = base.scheme();
     if scheme.starts_with("ca+") {
         scheme = &scheme[3..];
     } else if scheme.ends_with("+ca") {
@@ -46,88 +90,239 @@ fn resolve_ca_link(mirror: String, ware_hash: String) -> Result<Option<String>,
     } else {
         return Ok(None);
     };
-    if ware_hash.len() < 7 {
-        return Err(errf!("ware_hash must be at least 7 characters"));
-    }
+    let layout = mirror_layout(&mirror, &base, layouts)?;
+    mir_url.set_fragment(None);
     mir_url
         .path_segments_mut()
         .map_err(|_| "cannot be base")?
-        .push(&ware_hash[0..3])
-        .push(&ware_hash[3..6])
-        .push(&ware_hash);
+        .pop_if_empty()
+        .extend(layout.segments(pack_type, &ware_hash)?);
     // set_scheme will error if the target is a "special" scheme such as https and the prior scheme is not.
     // as such, this string replacement method works just fine.
     let new_url = [scheme, &mir_url[url::Position::AfterScheme..]].join("");
     Ok(Some(new_url))
 }
 
-fn join(
+// splits a ware ID into packtype and hash, or warns and returns None if it has no packtype,
+// which `validate` reports.
+fn split_ware_checked(ware_id: &str) -> Option<(String, String)> {
+    match ware_id.split_once(':') {
+        Some((pack_type, ware_hash)) => Some((pack_type.to_string(), ware_hash.to_string())),
+        None => {
+            eprintln!(r#"WARNING: skipping ware ID "{ware_id}" without a packtype"#);
+            None
+        }
+    }
+}
+
+// the priority of a mirror as written in _mirrors.json: that of the longest matching prefix, or 0.
+fn priority(mirror: &str, priorities: &IndexMap<String, i64>) -> i64 {
+    priorities
+        .iter()
+        .filter(|(prefix, _)| mirror.starts_with(prefix.as_str()))
+        .max_by_key(|(prefix, _)| prefix.len())
+        .map_or(0, |(_, p)| *p)
+}
+
+/// Resolves every ware to its mirror locations. `byModule` mirrors are resolved by the resolver
+/// registered for the ware's packtype; `byWare` links are used as they are, or expanded if they
+/// are content-addressable.
+/// Locations are ordered by the priority of the mirror they come from, highest first,
+/// and alphabetically among mirrors of the same priority.
+pub fn join(
     mirrors_capsule: CatalogMirrorsCapsule,
     releases: BTreeMap<String, String>,
-) -> Result<BTreeMap<String, BTreeSet<String>>, Box<dyn Error>> {
-    let mut result: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
-    let mut cat_mirrors: CatalogMirrors;
-    match mirrors_capsule {
-        CatalogMirrorsCapsule::V1(m) => cat_mirrors = m,
-    }
+    resolve: &ResolveOptions,
+) -> Result<ResolvedWares, Box<dyn Error>> {
+    // every location with the highest priority of the mirrors it was resolved from.
+    let mut result: BTreeMap<String, BTreeMap<String, i64>> = BTreeMap::new();
+    let mut cat_mirrors: CatalogMirrors = match mirrors_capsule {
+        CatalogMirrorsCapsule::V1(m) => m,
+        CatalogMirrorsCapsule::Unknown { version, .. } => {
+            return Err(errf!("unknown mirrors capsule version {version}"))
+        }
+    };
+    let priorities = &resolve.priorities;
+    let insert = |entry: &mut BTreeMap<String, i64>, location: String, priority: i64| {
+        let p = entry.entry(location).or_insert(priority);
+        *p = (*p).max(priority);
+    };
     // insert all wares with explicit links
     for (ware_id, loc) in cat_mirrors.by_ware.iter() {
-        let (_, ware_hash) = split_ware(ware_id.clone());
+        let Some((pack_type, ware_hash)) = split_ware_checked(ware_id) else {
+            continue;
+        };
         for item in loc.iter() {
-            let link_result = resolve_ca_link(item.clone(), ware_hash.clone());
+            let link_result = resolve_ca_link(
+                item.clone(),
+                &pack_type,
+                ware_hash.clone(),
+                &resolve.layouts,
+            );
             if let Err(e) = link_result {
                 eprintln!("unable to process link for {ware_id}: {item}: {}", e);
                 continue;
             };
-            let entry = result.entry(ware_id.clone()).or_insert(BTreeSet::new());
-            match link_result.unwrap() {
-                None => {
-                    entry.insert(item.clone());
-                }
-                Some(l) => {
-                    entry.insert(l);
-                }
-            };
+            let entry = result.entry(ware_id.clone()).or_default();
+            let location = link_result.unwrap().unwrap_or_else(|| item.clone());
+            insert(entry, location, priority(item, priorities));
         }
     }
 
     // create explicit links for by-module
     for (release_id, ware_id) in releases {
         let (module, _, _) = split_release(release_id.clone());
-        let (pack_type, ware_hash) = split_ware(ware_id.clone());
+        let Some((pack_type, ware_hash)) = split_ware_checked(&ware_id) else {
+            continue;
+        };
         let pack_mirrors = cat_mirrors
             .by_module
             .entry(module.clone())
-            .or_insert_with(IndexMap::new)
+            .or_default()
             .entry(pack_type.clone())
-            .or_insert_with(BTreeSet::new);
+            .or_default();
 
-        let ware_mirrors = result.entry(ware_id).or_insert(BTreeSet::new());
+        let ctx = ResolveContext {
+            module: &module,
+            pack_type: &pack_type,
+            layouts: &resolve.layouts,
+        };
+        let resolver = resolve.resolvers.get(&pack_type);
+        let ware_mirrors = result.entry(ware_id).or_default();
         for mirror in pack_mirrors.iter() {
-            if pack_type == "git" {
-                // could start handling specific git hosts such as
-                // https://github.com/warptools/warpforge/archive/e3ec637a29aee4874de2ce2e70e9c9e85761ce22.zip
-                // but that seems somewhat pointless since that is not how the wares are actually retrieved.
-                ware_mirrors.insert(mirror.clone());
-                continue;
-            }
-            let link = resolve_ca_link(mirror.clone(), ware_hash.clone())?;
-            match link {
-                None => {
-                    eprintln!("by module mirrors must have content-addressable scheme (e.g. ca+https): {module}:{pack_type} = {mirror}");
-                    continue;
-                }
-                Some(l) => {
-                    ware_mirrors.insert(l);
-                }
+            let p = priority(mirror, priorities);
+            for location in resolver.resolve(mirror, &ware_hash, &ctx)? {
+                insert(ware_mirrors, location, p);
             }
         }
     }
-    Ok(result)
+    let ordered = result
+        .into_iter()
+        .map(|(ware_id, locations)| {
+            let mut locations: Vec<(String, i64)> = locations.into_iter().collect();
+            // the sort is stable, so locations of equal priority stay in alphabetical order.
+            locations.sort_by_key(|(_, p)| -p);
+            (ware_id, locations.into_iter().map(|(l, _)| l).collect())
+        })
+        .collect();
+    Ok(ordered)
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::testutil::synthetic_catalog;
+    use indexmap::IndexMap;
+    use std::collections::BTreeMap;
+    use std::time::{Duration, Instant};
+
+    fn resolve_with_threads(dir: &std::path::Path, threads: usize) -> (String, Duration) {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()
+            .unwrap();
+        pool.install(|| {
+            let start = Instant::now();
+            let result = super::resolve_all(dir, &Default::default(), &Default::default()).unwrap();
+            let elapsed = start.elapsed();
+            (serde_json::to_string_pretty(&result).unwrap(), elapsed)
+        })
+    }
+
+    #[test]
+    fn test_parallel_matches_sequential() {
+        let catalog = synthetic_catalog(40, 3);
+        let (sequential, _) = resolve_with_threads(&catalog.path, 1);
+        let (parallel, _) = resolve_with_threads(&catalog.path, 8);
+        assert_eq!(sequential, parallel);
+        assert!(sequential.contains("https://example.org/wares/000/000/"));
+    }
+
+    // cargo test --release -- --ignored --nocapture bench_parallel_walk
+    #[test]
+    #[ignore]
+    fn bench_parallel_walk() {
+        let catalog = synthetic_catalog(5000, 4);
+        let threads = std::thread::available_parallelism().map_or(4, |n| n.get());
+        let (sequential, seq_time) = resolve_with_threads(&catalog.path, 1);
+        let (parallel, par_time) = resolve_with_threads(&catalog.path, threads);
+        assert_eq!(sequential, parallel);
+        println!(
+            "sequential: {seq_time:?}, {threads} threads: {par_time:?}, speedup: {:.2}x",
+            seq_time.as_secs_f64() / par_time.as_secs_f64()
+        );
+    }
+
+    #[test]
+    fn test_priority_order() {
+        let mirrors: crate::catalog_mirrors::CatalogMirrorsCapsule =
+            serde_json::from_value(serde_json::json!({"catalogmirrors.v1": {
+                "byWare": {"tar:abcdefgh": ["https://ftp.example.org/foo.tgz"]},
+                "byModule": {"example.org/foo": {"tar": [
+                    "ca+https://a.example.org",
+                    "ca+https://cdn.example.org",
+                ]}}
+            }}))
+            .unwrap();
+        let releases = BTreeMap::from([("example.org/foo:v1:src".into(), "tar:abcdefgh".into())]);
+        let result = super::join(mirrors.clone(), releases.clone(), &Default::default()).unwrap();
+        assert_eq!(
+            result["tar:abcdefgh"],
+            vec![
+                "https://a.example.org/abc/def/abcdefgh",
+                "https://cdn.example.org/abc/def/abcdefgh",
+                "https://ftp.example.org/foo.tgz",
+            ]
+        );
+
+        // the longest matching prefix wins.
+        let resolve = super::ResolveOptions {
+            priorities: IndexMap::from([
+                ("https://ftp.".into(), -1),
+                ("ca+https://".into(), 1),
+                ("ca+https://cdn.".into(), 5),
+            ]),
+            ..Default::default()
+        };
+        let result = super::join(mirrors, releases, &resolve).unwrap();
+        assert_eq!(
+            result["tar:abcdefgh"],
+            vec![
+                "https://cdn.example.org/abc/def/abcdefgh",
+                "https://a.example.org/abc/def/abcdefgh",
+                "https://ftp.example.org/foo.tgz",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_config_layouts() {
+        let mirrors: crate::catalog_mirrors::CatalogMirrorsCapsule =
+            serde_json::from_value(serde_json::json!({"catalogmirrors.v1": {
+                "byModule": {"example.org/foo": {"tar": [
+                    "ca+https://a.example.org",
+                    "ca+https://b.example.org#layout=flat",
+                ]}}
+            }}))
+            .unwrap();
+        let releases = BTreeMap::from([("example.org/foo:v1:src".into(), "tar:abcdefgh".into())]);
+        let resolve = super::ResolveOptions {
+            layouts: IndexMap::from([
+                ("ca+https://a.example.org".into(), "packtype/ab/cd".into()),
+                ("ca+https://b.example.org".into(), "ab/cd".into()),
+            ]),
+            ..Default::default()
+        };
+        let result = super::join(mirrors, releases, &resolve).unwrap();
+        // the fragment wins over the config.
+        assert_eq!(
+            result["tar:abcdefgh"],
+            vec![
+                "https://a.example.org/tar/ab/cd/abcdefgh",
+                "https://b.example.org/abcdefgh",
+            ]
+        );
+    }
+
     #[test]
     fn test_split_release() {
         let result = super::split_release("a:b:c:d".into());
@@ -145,6 +340,10 @@ mod tests {
 
     #[test]
     fn test_resolve_ca_link() {
+        let layouts = indexmap::IndexMap::from([(
+            String::from("ca+https://sharded.example.com"),
+            String::from("packtype/ab/cd"),
+        )]);
         let test_cases = vec![
             (
                 "ca+http://example.com",
@@ -156,10 +355,38 @@ mod tests {
                 "abcdefg",
                 Some(String::from("http://example.com/foo/abc/def/abcdefg")),
             ),
+            (
+                "ca+file:///srv/wares",
+                "abcdefg",
+                Some(String::from("file:///srv/wares/abc/def/abcdefg")),
+            ),
+            (
+                "ca+file:///srv/wares/",
+                "abcdefg",
+                Some(String::from("file:///srv/wares/abc/def/abcdefg")),
+            ),
+            (
+                "ca+https://example.com/flat#layout=flat",
+                "abcdefg",
+                Some(String::from("https://example.com/flat/abcdefg")),
+            ),
+            (
+                "ca+https://sharded.example.com",
+                "abcdefg",
+                Some(String::from(
+                    "https://sharded.example.com/tar/ab/cd/abcdefg",
+                )),
+            ),
+            (
+                "ca+https://sharded.example.com#layout=abc/def",
+                "abcdefg",
+                Some(String::from("https://sharded.example.com/abc/def/abcdefg")),
+            ),
             ("http://example.com/foo", "abcdefg", None),
         ];
         for (mirror, ware_hash, expected) in test_cases {
-            let result = super::resolve_ca_link(mirror.into(), ware_hash.into()).unwrap();
+            let result =
+                super::resolve_ca_link(mirror.into(), "tar", ware_hash.into(), &layouts).unwrap();
             assert_eq!(result, expected)
         }
     }
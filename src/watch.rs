@@ -0,0 +1,183 @@
+use notify::event::{CreateKind, ModifyKind, RemoveKind};
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+use similar::{ChangeTag, TextDiff};
+use std::error::Error;
+use std::ffi::OsStr;
+use std::fs;
+use std::path::Path;
+use std::sync::mpsc;
+use std::time::Duration;
+
+use crate::cache::CACHE_FILE_NAME;
+use crate::ignore::{Ignore, IGNORE_FILE_NAME};
+
+// changes arriving within this window are handled by a single re-render.
+const DEBOUNCE: Duration = Duration::from_millis(200);
+
+// whether the path of an event of `kind` is or was a directory. A removed or renamed path no
+// longer tells, so it is assumed to be one unless the event says otherwise.
+fn may_be_dir(path: &Path, kind: EventKind) -> bool {
+    match kind {
+        EventKind::Create(CreateKind::File) | EventKind::Remove(RemoveKind::File) => false,
+        EventKind::Create(CreateKind::Folder)
+        | EventKind::Remove(_)
+        | EventKind::Modify(ModifyKind::Name(_)) => true,
+        _ => path.is_dir(),
+    }
+}
+
+/// Reports whether a change of `kind` to `path` can affect the output of the catalog walkers,
+/// which skip the paths `ignore` skips.
+pub fn is_relevant(path: &Path, kind: EventKind, ignore: &Ignore) -> bool {
+    let file_name = path.file_name().and_then(OsStr::to_str).unwrap_or("");
+    if file_name == CACHE_FILE_NAME || ignore.skips(path) {
+        return false;
+    }
+    if file_name == "_module.json" || file_name == "_mirrors.json" || file_name == IGNORE_FILE_NAME
+    {
+        return true;
+    }
+    if path.parent().and_then(Path::file_name) == Some(OsStr::new("_releases")) {
+        return true;
+    }
+    // a directory may contain any of the above.
+    may_be_dir(path, kind) && !ignore.is_ignored(path, true)
+}
+
+// reading the catalog produces access events, which must not trigger another read.
+fn changes_catalog(event: &notify::Result<Event>, ignore: &Ignore) -> bool {
+    match event {
+        Ok(e) => !e.kind.is_access() && e.paths.iter().any(|p| is_relevant(p, e.kind, ignore)),
+        Err(_) => false,
+    }
+}
+
+/// Returns a line diff of two outputs, with lines prefixed by "+" or "-".
+/// Unchanged lines are omitted. An empty string means the outputs are identical.
+pub fn diff(old: &str, new: &str) -> String {
+    let mut result = String::new();
+    for change in TextDiff::from_lines(old, new).iter_all_changes() {
+        let sign = match change.tag() {
+            ChangeTag::Equal => continue,
+            ChangeTag::Delete => "-",
+            ChangeTag::Insert => "+",
+        };
+        result.push_str(sign);
+        result.push_str(change.value());
+        if change.missing_newline() {
+            result.push('\n');
+        }
+    }
+    result
+}
+
+/// Blocks forever, calling `on_change` once after every burst of changes to the catalog at `root`.
+pub fn wait_for_changes(root: &Path, mut on_change: impl FnMut()) -> Result<(), Box<dyn Error>> {
+    // events name canonical paths, which the skip rules must be relative to.
+    let root = fs::canonicalize(root)?;
+    let mut ignore = Ignore::load(&root)?;
+    let (tx, rx) = mpsc::channel::<notify::Result<Event>>();
+    let mut watcher = notify::recommended_watcher(tx)?;
+    watcher.watch(&root, RecursiveMode::Recursive)?;
+    loop {
+        let event = rx.recv()?;
+        let mut relevant = changes_catalog(&event, &ignore);
+        if let Err(e) = event {
+            eprintln!("WARNING: watch error: {e}");
+        }
+        while let Ok(event) = rx.recv_timeout(DEBOUNCE) {
+            relevant |= changes_catalog(&event, &ignore);
+        }
+        if relevant {
+            // the skip rules may have changed as well.
+            match Ignore::load(&root) {
+                Ok(i) => ignore = i,
+                Err(e) => eprintln!("WARNING: keeping the previous skip rules: {e}"),
+            }
+            eprintln!("catalog changed, re-reading {}", root.display());
+            on_change();
+        }
+    }
+}
+
+/// Prints the output of `render` and prints it again every time the catalog at `root` changes.
+/// With `only_diff` set, later outputs are printed as a diff against the previous one.
+/// Errors from `render` are reported on stderr and the watch continues.
+pub fn run(
+    root: &Path,
+    only_diff: bool,
+    mut render: impl FnMut() -> Result<String, Box<dyn Error>>,
+) -> Result<(), Box<dyn Error>> {
+    let mut previous = String::new();
+    let mut emit = move || match render() {
+        Ok(output) => {
+            if !only_diff || previous.is_empty() {
+                println!("{output}");
+            } else {
+                print!("{}", diff(&previous, &output));
+            }
+            previous = output;
+        }
+        Err(e) => eprintln!("Error: {e}"),
+    };
+    emit();
+    wait_for_changes(root, emit)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_relevant() {
+        use notify::event::{DataChange, RenameMode};
+        let write = EventKind::Modify(ModifyKind::Data(DataChange::Content));
+        let renamed = EventKind::Modify(ModifyKind::Name(RenameMode::From));
+        let removed_dir = EventKind::Remove(RemoveKind::Folder);
+        let removed_file = EventKind::Remove(RemoveKind::File);
+        let created_file = EventKind::Create(CreateKind::File);
+        let cases = vec![
+            ("cat/warpsys.org/bash/_module.json", write, true),
+            ("cat/warpsys.org/bash/_mirrors.json", removed_file, true),
+            (
+                "cat/warpsys.org/bash/_releases/v1.0.json",
+                created_file,
+                true,
+            ),
+            // namespace directories have a dot in their name.
+            ("cat/warpsys.org", renamed, true),
+            ("cat/warpsys.org", removed_dir, true),
+            ("cat/warpsys.org/bash", renamed, true),
+            ("cat/warpsys.org/bash/README", write, false),
+            ("cat/warpsys.org/bash/README.md", removed_file, false),
+            ("cat/warpsys.org/bash/_module.json.swp", write, false),
+            ("cat/.catalog-manifest-cache", write, false),
+            ("cat/.catalogignore", write, true),
+            ("cat/.git", removed_dir, false),
+            ("cat/.git/index", write, false),
+            ("cat/.git/HEAD", renamed, false),
+            ("cat/.git/refs/heads/main", created_file, false),
+            ("cat/warpsys.org/.hg/store", write, false),
+            ("cat/warpsys.org/CVS/Entries", write, false),
+            ("cat/warpsys.org/bash~/_module.json", write, false),
+            ("cat/drafts/foo/_module.json", write, false),
+            ("cat/warpsys.org/drafts", renamed, false),
+        ];
+        let ignore = Ignore::parse(Path::new("cat"), "drafts/\n").unwrap();
+        for (path, kind, expected) in cases {
+            assert_eq!(
+                is_relevant(Path::new(path), kind, &ignore),
+                expected,
+                "{path} {kind:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_diff() {
+        let old = "{\n  \"a\": 1,\n  \"b\": 2\n}";
+        let new = "{\n  \"a\": 1,\n  \"b\": 3\n}";
+        assert_eq!(diff(old, new), "-  \"b\": 2\n+  \"b\": 3\n");
+        assert_eq!(diff(old, old), "");
+    }
+}